@@ -1,73 +1,108 @@
-use std::fmt;
+use std::{collections::HashMap, fmt};
 
-const REG_IP: u16 = 125;
-const REG_SP: u16 = 126;
-const REG_FLAGS: u16 = 127;
+use crate::{conditional, diagnostic::Diagnostic, header::{Header, DEFAULT_STACK, FORMAT_VERSION}, isa::{self, OpSpec}, lexer::SourceMap};
 
+pub const REG_IP: u16 = 125;
+pub const REG_SP: u16 = 126;
+pub const REG_FLAGS: u16 = 127;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Cond {
     LT, GT,
     EQ, NEQ
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum Arg {
     Reg(u16),
     Imm(u32),
     Label(String)
 }
 
-pub enum Instruction {
-    Mov(Arg, Arg),
-    Cmp(Arg, Arg),
-    Branch(Arg),
-    BranchCond(Cond, Arg),
-    Call(Arg),
-    Calljs(Arg),
-    Push(Arg),
-    Str(Arg, Arg),
-
-    Add(Arg, Arg, Arg),
-    Inc(Arg),
-    Dec(Arg),
-    Cli()
+// Instruction as written in assembly; its encoding is looked up in `isa::OPS`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    // Base mnemonic, e.g. "b" for `blt`
+    pub mnemonic: &'static str,
+    pub cond: Option<Cond>,
+    pub args: Vec<Arg>
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Label {
     pub name: String,
     pub instructions: Vec<Instruction>
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Program {
     pub labels: Vec<Label>,
+    // Data section, placed right after the code
+    pub data: Vec<u32>,
+    // Constants for conditional assembly, from `.define` or the command line
+    pub defines: HashMap<String, String>,
+    // Label execution starts at, from `.entry`; the start of the code if unset
+    pub entry: Option<String>,
+    // Initial stack pointer, from `.stack`
+    pub stack: u32,
+    // Definitions of each numeric label seen so far while parsing
+    pub anonymous: HashMap<String, usize>,
+    // Problems found while parsing, located in the source passed to `parse`
+    pub diagnostics: Vec<Diagnostic>,
     pub tok: usize
 }
 
 pub trait Parser {
-    fn skip_until(&mut self, program: &String, c: char) -> String;
-    fn skip_until_whitespace(&mut self, program: &String) -> String;
-    fn skip_whitespace(&mut self, program: &String);
-    fn next_arg(&mut self, program: &String, is_last: bool) -> Arg;
+    fn skip_until(&mut self, program: &str, c: char) -> String;
+    fn skip_until_whitespace(&mut self, program: &str) -> String;
+    fn skip_whitespace(&mut self, program: &str);
+    fn next_arg(&mut self, program: &str, is_last: bool) -> Arg;
     fn parse_register(&mut self, reg: String) -> u16;
-    fn parse_arg(&mut self, arg: String) -> Arg;
+    fn parse_arg(&mut self, arg: String) -> Option<Arg>;
     fn parse(&mut self, program: String);
+    fn parse_directive(&mut self, program: &str, directive: String);
 }
 
-fn arg_count(insn: &Instruction) -> usize {
-    match insn {
-        Instruction::Add(_, _, _) => 3,
+impl Instruction {
+    // Instruction for a source mnemonic such as `blt`, None if `isa::OPS` has no such
+    // mnemonic taking that many operands
+    pub fn new(mnemonic: &str, args: Vec<Arg>) -> Option<Instruction> {
+        let (base, cond) = isa::split_mnemonic(mnemonic)?;
+        let spec = isa::OPS.iter().find(|op| op.mnemonic == base && op.is_conditional() == cond.is_some())?;
+        if spec.arity() != args.len() { return None }
 
-        Instruction::Mov(_, _) | Instruction::Cmp(_, _) | Instruction::BranchCond(_, _) |
-        Instruction::Str(_, _) => 2,
+        Some(Instruction { mnemonic: spec.mnemonic, cond: cond.map(|spec| spec.cond), args })
+    }
 
-        Instruction::Inc(_) | Instruction::Dec(_) |  Instruction::Branch(_) |
-        Instruction::Push(_) | Instruction::Call(_) | Instruction::Calljs(_) => 1,
+    // Mnemonic as written in assembly, including the condition
+    pub fn source_mnemonic(&self) -> String {
+        match self.cond {
+            Some(cond) => format!("{}{}", self.mnemonic, isa::cond_spec(cond).suffix),
+            None => self.mnemonic.to_string()
+        }
+    }
 
-        Instruction::Cli() => 0
+    // Encoding chosen for the instruction's operand kinds, None if no encoding accepts them
+    pub fn spec(&self) -> Option<&'static OpSpec> {
+        let args: Vec<&Arg> = self.args.iter().collect();
+        isa::select(self.mnemonic, self.cond.is_some(), &args)
+    }
+
+    pub fn size(&self) -> usize {
+        match self.spec() {
+            Some(spec) => spec.size(),
+            None => self.args.len() + 1 + self.cond.map_or(0, |_| 1)
+        }
     }
 }
 
 impl Program {
     pub fn new() -> Self {
-        Self { labels: vec![], tok: 0 }
+        Self { labels: vec![], data: vec![], defines: HashMap::new(), entry: None, stack: DEFAULT_STACK, anonymous: HashMap::new(), diagnostics: vec![], tok: 0 }
+    }
+
+    pub fn define(&mut self, name: &str, value: &str) {
+        self.defines.insert(name.to_string(), value.to_string());
     }
 
     pub fn find_label_start_index(&self, needle: &String) -> usize {
@@ -77,160 +112,175 @@ impl Program {
                 return result;
             }
             for insn in &label.instructions {
-                result += arg_count(insn) + 1;
+                result += insn.size();
             }
         }
 
         0
     }
 
-    pub fn assemble(&self) -> String {
+    // Word offset of every label, in program order
+    pub fn symbols(&self) -> Vec<(String, usize)> {
+        let mut result = vec![];
+        let mut offset = 0usize;
+        for label in &self.labels {
+            result.push((label.name.clone(), offset));
+            for insn in &label.instructions {
+                offset += insn.size();
+            }
+        }
+
+        result
+    }
+
+    pub fn symbol_table(&self) -> HashMap<String, usize> {
+        self.symbols().into_iter().collect()
+    }
+
+    pub fn code_size(&self) -> usize {
+        self.labels.iter()
+            .flat_map(|label| label.instructions.iter())
+            .map(|insn| insn.size())
+            .sum()
+    }
+
+    pub fn entry_offset(&self) -> Result<usize, String> {
+        match &self.entry {
+            Some(entry) => match self.symbols().iter().find(|(name, _)| name == entry) {
+                Some((_, offset)) => Ok(*offset),
+                None => Err(format!("entry label .{} is not defined", entry))
+            },
+            None => Ok(0)
+        }
+    }
+
+    // Header for an image of the code, followed by the data section if `with_data` is set
+    pub fn header(&self, with_data: bool) -> Result<Header, String> {
+        Ok(Header {
+            version: FORMAT_VERSION,
+            entry: self.entry_offset()? as u32,
+            stack: self.stack,
+            code_size: self.code_size() as u32,
+            data_size: if with_data { self.data.len() as u32 } else { 0 }
+        })
+    }
+
+    // Opcode and operand words of a single instruction. Fails on operands no encoding accepts
+    // and on references to undefined labels.
+    fn encode_instruction(&self, insn: &Instruction, symbols: &HashMap<String, usize>) -> Result<(&'static OpSpec, Vec<u32>), String> {
+        let spec = match insn.spec() {
+            Some(spec) => spec,
+            None => return Err(format!("no encoding for {}", insn))
+        };
+
+        let mut words = vec![];
+        if let Some(cond) = insn.cond {
+            words.push(isa::cond_spec(cond).value);
+        }
+        for arg in &insn.args {
+            words.push(match arg {
+                Arg::Reg(reg) => *reg as u32,
+                Arg::Imm(value) => *value,
+                Arg::Label(label) => match symbols.get(label) {
+                    Some(offset) => *offset as u32,
+                    None => return Err(format!("undefined label .{}", label))
+                }
+            });
+        }
+
+        Ok((spec, words))
+    }
+
+    // Numeric encoding of every instruction, in program order
+    pub fn encode_instructions(&self) -> Result<Vec<Vec<u32>>, String> {
+        let symbols = self.symbol_table();
+        let mut result = vec![];
+        for label in &self.labels {
+            for insn in &label.instructions {
+                let (spec, words) = self.encode_instruction(insn, &symbols)?;
+                let mut encoded = vec![spec.opcode];
+                encoded.extend(words);
+                result.push(encoded);
+            }
+        }
+
+        Ok(result)
+    }
+
+    // Numeric image of the code, as loaded by the VM
+    pub fn encode(&self) -> Result<Vec<u32>, String> {
+        Ok(self.encode_instructions()?.concat())
+    }
+
+    pub fn assemble(&self) -> Result<String, String> {
+        let symbols = self.symbol_table();
         let mut result = String::new();
 
         for label in &self.labels {
             for insn in &label.instructions {
-                match insn {
-                    Instruction::Mov(arg1, arg2) => {
-                        if let Arg::Reg(dest) = arg1 {
-                            match arg2 {
-                                Arg::Imm(value) => result += format!("Op.MOV_CONST, {}, {},\n", dest, value).as_str(),
-                                Arg::Reg(reg) => result += format!("Op.MOV_REG, {}, {},\n", dest, reg).as_str(),
-                                Arg::Label(_) => panic!("ERROR: Wrong source type for mov")
-                            }
-                        } else {
-                            panic!("ERROR: Wrong destination type for mov")
-                        }
-                    },
-                    Instruction::Branch(arg) => {
-                        if let Arg::Label(target) = arg {
-                            result += format!("Op.BRANCH_CONST, {},\n", self.find_label_start_index(target)).as_str();
-                        } else if let Arg::Reg(reg) = arg {
-                            result += format!("Op.BRANCH_REG, {},\n", reg).as_str();
-                        } else {
-                            panic!("ERROR: Wrong argument type for branch")
-                        }
-                    },
-                    Instruction::BranchCond(cond, arg) => {
-                        let cond_str = match cond {
-                            Cond::EQ => "BranchType.EQ",
-                            Cond::GT => "BranchType.GT",
-                            Cond::LT => "BranchType.LT",
-                            Cond::NEQ => "BranchType.NEQ"
-                        };
-
-                        if let Arg::Label(target) = arg {
-                            result += format!("Op.BRANCH_COND_CONST, {}, {},\n", cond_str, self.find_label_start_index(target)).as_str();
-                        } else if let Arg::Reg(reg) = arg {
-                            result += format!("Op.BRANCH_COND_REG, {}, {},\n", cond_str, reg).as_str();
-                        } else {
-                            panic!("ERROR: Wrong argument type for branch with condition")
-                        }
-                    },
-                    Instruction::Cmp(arg1, arg2) => {
-                        if let Arg::Reg(reg1) = arg1 {
-                            if let Arg::Imm(value) = arg2 {
-                                result += format!("Op.CMP_REG_CONST, {}, {},\n", reg1, value).as_str();
-                            } else if let Arg::Reg(reg2) = arg2 {
-                                result += format!("Op.CMP_REG_REG, {}, {},\n", reg1, reg2).as_str();
-                            } else {
-                                panic!("ERROR: Wrong argument type for cmp (argument 1 must be a reg or imm)")
-                            }
-                        } else {
-                            panic!("ERROR: Wrong argument type for cmp (argument 0 must be a reg)")
-                        }
-                    },
-                    Instruction::Add(arg1, arg2, arg3) => {
-                        if let Arg::Reg(dst) = arg1 {
-                            if let Arg::Reg(reg1) = arg2 {
-                                if let Arg::Reg(reg2) = arg3 {
-                                    result += format!("Op.ADD_REG, {}, {}, {},\n", dst, reg1, reg2).as_str();
-                                } else if let Arg::Imm(value) = arg3 {
-                                    result += format!("Op.ADD_CONST, {}, {}, {},\n", dst, reg1, value).as_str();
-                                } else {
-                                    panic!("ERROR: Wrong argument type for add (argument 2 must be a reg or imm)")
-                                }
-                            } else {
-                                panic!("ERROR: Wrong argument type for add (argument 1 must be a reg)")
-                            }
-                        } else {
-                            panic!("ERROR: Wrong argument type for add (argument 0 must be a reg)")
-                        }
-                    },
-                    Instruction::Push(arg1) => {
-                        if let Arg::Reg(reg) = arg1 {
-                            result += format!("Op.PUSH_REG, {},\n", reg).as_str();
-                        } else if let Arg::Imm(value) = arg1 {
-                            result += format!("Op.PUSH_CONST, {},\n", value).as_str();
-                        } else {
-                            panic!("ERROR: Wrong argument type for push (argument 0 must be a reg or imm)")
-                        }
-                    },
-                    Instruction::Str(arg1, arg2) => {
-                        if let Arg::Reg(dst_reg) = arg1 {
-                            if let Arg::Reg(src_reg) = arg2 {
-                                result += format!("Op.STR_REG_TO_REG, {}, {},\n", dst_reg, src_reg).as_str();
-                            } else if let Arg::Imm(src_value) = arg2 {
-                                result += format!("Op.STR_CONST_TO_REG, {}, {},\n", dst_reg, src_value).as_str();
-                            } else {
-                                panic!("ERROR: Wrong argument type for str (argument 1 must be a reg or imm)")
-                            }
-                        } else if let Arg::Imm(dst_value) = arg1 {
-                            if let Arg::Reg(src_reg) = arg2 {
-                                result += format!("Op.STR_REG_TO_CONST, {}, {},\n", dst_value, src_reg).as_str();
-                            } else if let Arg::Imm(src_value) = arg2 {
-                                result += format!("Op.STR_CONST_TO_CONST, {}, {},\n", dst_value, src_value).as_str();
-                            } else {
-                                panic!("ERROR: Wrong argument type for str (argument 1 must be a reg or imm)")
-                            }
-                        } else {
-                            panic!("ERROR: Wrong argument type for str (argument 0 must be a reg or imm)")
-                        }
-                    },
-                    Instruction::Call(arg1) => {
-                        if let Arg::Reg(reg) = arg1 {
-                            result += format!("Op.CALL_REG, {},\n", reg).as_str();
-                        } else if let Arg::Imm(value) = arg1 {
-                            result += format!("Op.CALL_CONST, {},\n", value).as_str();
-                        } else if let Arg::Label(label) = arg1 {
-                            result += format!("Op.CALL_CONST, {},\n", self.find_label_start_index(label)).as_str();
-                        }
-                    },
-                    Instruction::Calljs(arg1) => {
-                        if let Arg::Reg(reg) = arg1 {
-                            result += format!("Op.CALL_JS_REG, {},\n", reg).as_str();
-                        } else if let Arg::Imm(value) = arg1 {
-                            result += format!("Op.CALL_JS_CONST, {},\n", value).as_str();
-                        } else {
-                            panic!("ERROR: Wrong argument type for calljs (argument 0 must be a reg or imm)")
-                        }
-                    },
-                    Instruction::Inc(arg) => {
-                        if let Arg::Reg(reg) = arg {
-                            result += format!("Op.INC, {},\n", reg).as_str();
-                        } else {
-                            panic!("ERROR: Wrong argument type for inc")
-                        }
-                    },
-                    Instruction::Dec(arg) => {
-                        if let Arg::Reg(reg) = arg {
-                            result += format!("Op.DEC, {},\n", reg).as_str();
-                        } else {
-                            panic!("ERROR: Wrong argument type for dec")
-                        }
-                    },
-                    Instruction::Cli() => {
-                        result += format!("Op.CLEAR_FLAGS,\n").as_str();
+                let (spec, words) = self.encode_instruction(insn, &symbols)?;
+                result += format!("Op.{},", spec.name).as_str();
+                for (operand, word) in spec.operands.iter().zip(words) {
+                    if *operand == isa::Operand::Condition {
+                        result += format!(" BranchType.{},", isa::cond_by_value(word).unwrap().name).as_str();
+                    } else {
+                        result += format!(" {},", word).as_str();
                     }
                 }
+                result += "\n";
             }
         }
 
-        result
-    }   
+        Ok(result)
+    }
+}
+
+impl Program {
+    // `1f` and `1b` refer to the next and the previous definition of the numeric label `1`.
+    // None if `arg` is not such a reference.
+    fn anonymous_reference(&self, arg: &str) -> Option<Result<String, String>> {
+        let (number, direction) = arg.split_at(arg.len().checked_sub(1)?);
+        if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) { return None }
+
+        let defined = self.anonymous.get(number).copied().unwrap_or(0);
+        match direction {
+            "f" => Some(Ok(format!("{}@{}", number, defined))),
+            "b" if defined > 0 => Some(Ok(format!("{}@{}", number, defined - 1))),
+            "b" => Some(Err(format!("{} refers to label {} before its definition", arg, number))),
+            _ => None
+        }
+    }
+
+    // Reports the `len` characters of source starting at character `start`
+    fn error(&mut self, program: &str, start: usize, len: usize, message: String) {
+        let offset = |index| program.char_indices().nth(index).map_or(program.len(), |(offset, _)| offset);
+        let span = SourceMap::new(program).span(offset(start), offset(start + len));
+        self.diagnostics.push(Diagnostic::error(message, span));
+    }
+
+    // Skips the rest of the line after an error
+    fn skip_line(&mut self, program: &str) {
+        while let Some(c) = program.chars().nth(self.tok) {
+            self.tok += 1;
+            if c == '\n' { break }
+        }
+    }
+
+    // Whether the next word in the source starts a label or directive
+    fn at_label_or_directive(&self, program: &str) -> bool {
+        let word: String = program.chars().skip(self.tok).take_while(|c| !c.is_whitespace()).collect();
+        word.is_empty() || word.starts_with('.') || word.ends_with(':')
+    }
+}
+
+impl Default for Program {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Parser for Program {
-    fn skip_until(&mut self, program: &String, until: char) -> String {
+    fn skip_until(&mut self, program: &str, until: char) -> String {
         let mut c = program.chars().nth(self.tok).unwrap();
         let mut result = String::new();
         while c != until {
@@ -247,7 +297,7 @@ impl Parser for Program {
         result
     }
 
-    fn skip_until_whitespace(&mut self, program: &String) -> String {
+    fn skip_until_whitespace(&mut self, program: &str) -> String {
         let mut c = program.chars().nth(self.tok).unwrap();
         let mut result = String::new();
         while c != ' ' && c != '\t' && c != '\n' && self.tok < program.len()  {
@@ -263,12 +313,10 @@ impl Parser for Program {
         result
     }
 
-    fn skip_whitespace(&mut self, program: &String) {
-        if self.tok >= program.len() - 1 { return }
-        let mut c = program.chars().nth(self.tok).unwrap();
-        while c == '\n' || c == '\t' || c == ' ' {
+    fn skip_whitespace(&mut self, program: &str) {
+        while let Some(c) = program.chars().nth(self.tok) {
+            if c != '\n' && c != '\t' && c != ' ' { break }
             self.tok += 1;
-            c = program.chars().nth(self.tok).unwrap();
         }
     }
 
@@ -276,89 +324,189 @@ impl Parser for Program {
         reg[1..].parse::<u16>().unwrap()
     }
 
-    fn parse_arg(&mut self, arg: String) -> Arg {
+    // None if `arg` is not a register, label or number
+    fn parse_arg(&mut self, arg: String) -> Option<Arg> {
+        if let Some(Ok(label)) = self.anonymous_reference(&arg) {
+            return Some(Arg::Label(label));
+        }
+
         match arg.as_str() {
-            "ip" => Arg::Reg(REG_IP),
-            "flgs" => Arg::Reg(REG_FLAGS),
-            "sp" => Arg::Reg(REG_SP),
-            _ => match arg.chars().nth(0).unwrap() {
-                '.' => Arg::Label(arg[1..].to_string()),
-                'r' => Arg::Reg(self.parse_register(arg)),
+            "ip" => Some(Arg::Reg(REG_IP)),
+            "flgs" => Some(Arg::Reg(REG_FLAGS)),
+            "sp" => Some(Arg::Reg(REG_SP)),
+            _ => match arg.chars().nth(0)? {
+                '.' => Some(Arg::Label(arg[1..].to_string())),
+                'r' => Some(Arg::Reg(self.parse_register(arg))),
                 '0' => {
                     if arg.len() > 1 {
                         match arg.chars().nth(1).unwrap() {
-                            'x' => Arg::Imm(u32::from_str_radix(&arg[2..], 16).unwrap()),
-                            'o' => Arg::Imm(u32::from_str_radix(&arg[2..], 8).unwrap()),
-                            'b' => Arg::Imm(u32::from_str_radix(&arg[2..], 2).unwrap()),
-                            _ => panic!("ERROR at parsing: wrong radix number: {}", arg),
+                            'x' => u32::from_str_radix(&arg[2..], 16).ok().map(Arg::Imm),
+                            'o' => u32::from_str_radix(&arg[2..], 8).ok().map(Arg::Imm),
+                            'b' => u32::from_str_radix(&arg[2..], 2).ok().map(Arg::Imm),
+                            _ => None
                         }
                     } else {
-                        Arg::Imm(0)
-                    } 
+                        Some(Arg::Imm(0))
+                    }
                 },
                 '1' | '2' | '3' | '4' |
-                '5' | '6' | '7' | '8' | '9' => 
-                    Arg::Imm(arg.parse::<u32>().unwrap()),
-                _ => panic!("ERROR at parsing: Wrong arg: {}", arg)
+                '5' | '6' | '7' | '8' | '9' =>
+                    arg.parse::<u32>().ok().map(Arg::Imm),
+                _ => None
             }
         }
     }
 
-    fn next_arg(&mut self, program: &String, is_last: bool) -> Arg {
+    fn next_arg(&mut self, program: &str, is_last: bool) -> Arg {
         let c: char = if is_last { '\n' } else { ',' };
-        let arg = self.skip_until(&program, c);
+        let start = self.tok;
+        let arg = self.skip_until(program, c);
         if self.tok < program.len() - 1 {
-            self.tok += 1; self.skip_whitespace(&program);
+            self.tok += 1; self.skip_whitespace(program);
+        }
+
+        // Kept as written so parsing can go on; the error stops the program from being used
+        if let Some(Err(message)) = self.anonymous_reference(&arg) {
+            self.error(program, start, arg.chars().count(), message);
+            return Arg::Label(arg);
         }
 
-        self.parse_arg(arg)
+        match self.parse_arg(arg.clone()) {
+            Some(arg) => arg,
+            None => panic!("ERROR at parsing: Wrong arg: {}", arg)
+        }
     }
 
+    // Labels written with an extra dot are local to the enclosing global label: `..loop:` inside
+    // `.main` is `main.loop`, and `.loop` refers to it from within `.main`. Numeric labels (`1:`)
+    // are referenced with `1f` and `1b`.
     fn parse(&mut self, program: String) {
-        while self.tok < program.len() {
-            let c = program.chars().nth(self.tok).unwrap();
-            if c == '.' {
-                self.tok += 1;
-                let label_name = self.skip_until(&program, ':');
-                self.tok += 1; 
-
-                let mut instructions: Vec<Instruction> = Vec::new();
-
-                loop {
-                    self.skip_whitespace(&program);
-
-                    let instruction_name = self.skip_until_whitespace(&program);
-                    self.skip_whitespace(&program);
-
-                    match instruction_name.as_str() {
-                        "add" => instructions.push(Instruction::Add(self.next_arg(&program, false), self.next_arg(&program, false), self.next_arg(&program, true))),
-                        "mov" => instructions.push(Instruction::Mov(self.next_arg(&program, false), self.next_arg(&program, true))),
-                        "str" => instructions.push(Instruction::Str(self.next_arg(&program, false), self.next_arg(&program, true))),
-                        "cmp" => instructions.push(Instruction::Cmp(self.next_arg(&program, false), self.next_arg(&program, true))),
-                        "b" =>  instructions.push(Instruction::Branch(self.next_arg(&program, true))),
-                        "blt" => instructions.push(Instruction::BranchCond(Cond::LT, self.next_arg(&program, true))),
-                        "bgt" => instructions.push(Instruction::BranchCond(Cond::GT, self.next_arg(&program, true))),
-                        "beq" => instructions.push(Instruction::BranchCond(Cond::EQ, self.next_arg(&program, true))),
-                        "bneq" => instructions.push(Instruction::BranchCond(Cond::NEQ, self.next_arg(&program, true))),
-                        "dec" => instructions.push(Instruction::Dec(self.next_arg(&program, true))),
-                        "inc" => instructions.push(Instruction::Inc(self.next_arg(&program, true))),
-                        "push" => instructions.push(Instruction::Push(self.next_arg(&program, true))),
-                        "call" => instructions.push(Instruction::Call(self.next_arg(&program, true))),
-                        "calljs" => instructions.push(Instruction::Calljs(self.next_arg(&program, true))),
-                        "cli" => instructions.push(Instruction::Cli()),
-                        _ => panic!("No such instruction: {}", instruction_name)
+        let (program, diagnostics) = conditional::preprocess(&program, &mut self.defines);
+        self.diagnostics.extend(diagnostics);
+        let first_label = self.labels.len();
+        // Enclosing global label of each parsed label, used to resolve local references
+        let mut scopes: Vec<Option<String>> = vec![];
+        let mut scope: Option<String> = None;
+
+        loop {
+            self.skip_whitespace(&program);
+            let c = match program.chars().nth(self.tok) {
+                Some(c) => c,
+                None => break
+            };
+
+            let start = self.tok;
+            if c == '.' { self.tok += 1 }
+            let name = self.skip_until_whitespace(&program);
+            let label_name = match name.strip_suffix(':') {
+                Some(label_name) if c != '.' && !label_name.is_empty() && label_name.chars().all(|c| c.is_ascii_digit()) => {
+                    let count = self.anonymous.entry(label_name.to_string()).or_insert(0);
+                    *count += 1;
+                    format!("{}@{}", label_name, *count - 1)
+                },
+                Some(label_name) if c == '.' => match (label_name.strip_prefix('.'), &scope) {
+                    (Some(local), Some(scope)) => format!("{}.{}", scope, local),
+                    (Some(local), None) => local.to_string(),
+                    (None, _) => {
+                        scope = Some(label_name.to_string());
+                        label_name.to_string()
                     }
+                },
+                None if c == '.' => {
+                    self.parse_directive(&program, name);
+                    continue;
+                },
+                // e.g. an instruction after `.word`, which ends the label's code
+                _ => {
+                    self.error(&program, start, name.chars().count(), format!("expected a label or directive, found '{}'", name));
+                    self.skip_line(&program);
+                    continue;
+                }
+            };
+            if self.labels.iter().any(|label| label.name == label_name) {
+                self.error(&program, start, self.tok - start, format!("duplicate label {}", label_name));
+            }
+
+            let mut instructions: Vec<Instruction> = Vec::new();
 
-                    if self.tok >= program.len() - 1 { break }
-                    if let Some(c) = program.chars().nth(self.tok) { 
-                        if c == '.' { break }
+            loop {
+                self.skip_whitespace(&program);
+                if self.at_label_or_directive(&program) { break }
+
+                let start = self.tok;
+                let instruction_name = self.skip_until_whitespace(&program);
+                self.skip_whitespace(&program);
+
+                let arity = match isa::arity(&instruction_name) {
+                    Some(arity) => arity,
+                    None => {
+                        self.error(&program, start, instruction_name.chars().count(), format!("no such instruction: {}", instruction_name));
+                        self.skip_line(&program);
+                        continue;
+                    }
+                };
+                let args = (0..arity).map(|i| self.next_arg(&program, i + 1 == arity)).collect();
+                instructions.push(Instruction::new(&instruction_name, args).unwrap());
+            }
+
+            self.labels.push(Label {
+                instructions,
+                name: label_name
+            });
+            scopes.push(scope.clone());
+        }
+
+        // `.name` refers to the local label of the enclosing scope if there is one
+        let symbols = self.symbol_table();
+        for (label, scope) in self.labels[first_label..].iter_mut().zip(scopes) {
+            let Some(scope) = scope else { continue };
+            for insn in label.instructions.iter_mut() {
+                for arg in insn.args.iter_mut() {
+                    if let Arg::Label(name) = arg {
+                        let local = format!("{}.{}", scope, name);
+                        if symbols.contains_key(&local) { *name = local }
                     }
                 }
+            }
+        }
+    }
 
-                self.labels.push(Label {
-                    instructions,
-                    name: label_name.clone()
-                });
+    // Called with the directive name just read, before the whitespace following it
+    fn parse_directive(&mut self, program: &str, directive: String) {
+        let directive_start = self.tok - directive.chars().count() - 1;
+        self.skip_whitespace(program);
+        match directive.as_str() {
+            "word" => {
+                let mut start = self.tok;
+                let line = self.skip_until(program, '\n');
+                for value in line.split(',') {
+                    let trimmed = value.trim();
+                    let value_start = start + value.chars().count() - value.trim_start().chars().count();
+                    match self.parse_arg(trimmed.to_string()) {
+                        Some(Arg::Imm(value)) => self.data.push(value),
+                        _ => self.error(program, value_start, trimmed.chars().count(), format!(".word expects immediate values, found '{}'", trimmed))
+                    }
+                    start += value.chars().count() + 1;
+                }
+            },
+            "entry" => {
+                let name = self.skip_until_whitespace(program);
+                self.entry = Some(name.strip_prefix('.').unwrap_or(&name).to_string());
+            },
+            "stack" => {
+                let start = self.tok;
+                let value = self.skip_until_whitespace(program);
+                match self.parse_arg(value.clone()) {
+                    Some(Arg::Imm(value)) => self.stack = value,
+                    _ => {
+                        self.error(program, start, value.chars().count(), format!(".stack expects an immediate value, found '{}'", value));
+                        self.skip_line(program);
+                    }
+                }
+            },
+            _ => {
+                self.error(program, directive_start, directive.chars().count() + 1, format!("unknown directive .{}", directive));
+                self.skip_line(program);
             }
         }
     }
@@ -379,20 +527,11 @@ impl fmt::Display for Program {
 
 impl fmt::Display for Instruction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Instruction::Add(dest, arg1, arg2) => write!(f, "Add({}, {}, {})", dest, arg1, arg2),
-            Instruction::Mov(dest, src) => write!(f, "Mov({}, {})", dest, src),
-            Instruction::Str(dest, src) => write!(f, "Str({}, {})", dest, src),
-            Instruction::BranchCond(cond, arg) => write!(f, "BranchCond({}, {})", cond, arg),
-            Instruction::Branch(target) => write!(f, "B({})", target),
-            Instruction::Cmp(arg1, arg2) => write!(f, "Cmp({}, {})", arg1, arg2),
-            Instruction::Inc(arg) => write!(f, "Inc({})", arg),
-            Instruction::Dec(arg) => write!(f, "Dec({})", arg),
-            Instruction::Push(arg) => write!(f, "Push({})", arg),
-            Instruction::Calljs(arg) => write!(f, "Calljs({})", arg),
-            Instruction::Call(arg) => write!(f, "Call({})", arg),
-            Instruction::Cli() => write!(f, "Cli()"),
-        }
+        let mnemonic = self.source_mnemonic();
+        let mut chars = mnemonic.chars();
+        let name: String = chars.next().map(|c| c.to_ascii_uppercase()).into_iter().chain(chars).collect();
+        let args: Vec<String> = self.args.iter().map(|arg| arg.to_string()).collect();
+        write!(f, "{}({})", name, args.join(", "))
     }
 }
 
@@ -408,11 +547,6 @@ impl fmt::Display for Arg {
 
 impl fmt::Display for Cond {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Cond::EQ => write!(f, "Cond.EQ"),
-            Cond::NEQ => write!(f, "Cond.NEQ"),
-            Cond::LT => write!(f, "Cond.LT"),
-            Cond::GT => write!(f, "Cond.GT"),
-        }   
+        write!(f, "Cond.{}", isa::cond_spec(*self).name)
     }
 }
@@ -0,0 +1,347 @@
+use std::fmt;
+
+use crate::lexer::{IntSuffix, Span};
+
+// Syntax tree produced by `parser::Parser`. Every node keeps the span of the source it was
+// parsed from.
+
+// Index of a declaration in `sema::SymbolTable`, filled in by semantic analysis
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SymbolId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IntKind {
+    Char,
+    Short,
+    Int,
+    Long
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Void,
+    Int { kind: IntKind, unsigned: bool },
+    Float,
+    Double,
+    Struct(String),
+    Pointer(Box<Type>),
+    // Element type and length, which may be left out (`int a[]`)
+    Array(Box<Type>, Option<usize>),
+    // Type of a function designator; only built by semantic analysis
+    Function { return_type: Box<Type>, params: Vec<Type>, variadic: bool }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Storage {
+    Static,
+    Extern
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranslationUnit {
+    pub items: Vec<ExternalDecl>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExternalDecl {
+    Function(FunctionDef),
+    Prototype(FunctionDecl),
+    Variable(VarDecl),
+    Struct(StructDecl)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub ty: Type,
+    pub name: Option<String>,
+    pub symbol: Option<SymbolId>,
+    pub span: Span
+}
+
+// Signature shared by prototypes and definitions
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionDecl {
+    pub name: String,
+    pub return_type: Type,
+    pub params: Vec<Param>,
+    pub variadic: bool,
+    // False for `()`, which leaves the parameters unspecified, as opposed to `(void)`
+    pub prototyped: bool,
+    pub storage: Option<Storage>,
+    pub symbol: Option<SymbolId>,
+    pub span: Span
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionDef {
+    pub decl: FunctionDecl,
+    pub body: Block
+}
+
+// One declarator of a declaration: `int a = 1, *b;` is two of them
+#[derive(Debug, Clone, PartialEq)]
+pub struct VarDecl {
+    pub name: String,
+    pub ty: Type,
+    pub storage: Option<Storage>,
+    pub is_const: bool,
+    pub init: Option<Initializer>,
+    pub symbol: Option<SymbolId>,
+    pub span: Span
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Initializer {
+    Expr(Expr),
+    // `{ 1, 2, 3 }`
+    List(Vec<Initializer>, Span)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub ty: Type,
+    pub name: String,
+    pub span: Span
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructDecl {
+    pub name: String,
+    pub fields: Vec<Field>,
+    pub span: Span
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub stmts: Vec<Stmt>,
+    pub span: Span
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    Decl(VarDecl),
+    Expr(Expr),
+    Block(Block),
+    // A lone `;`
+    Empty,
+    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
+    While(Expr, Box<Stmt>),
+    DoWhile(Box<Stmt>, Expr),
+    For {
+        init: Option<ForInit>,
+        condition: Option<Expr>,
+        step: Option<Expr>,
+        body: Box<Stmt>
+    },
+    Break,
+    Continue,
+    Return(Option<Expr>)
+}
+
+// First clause of a `for`, whose variables are scoped to the loop
+#[derive(Debug, Clone, PartialEq)]
+pub enum ForInit {
+    Decl(Vec<VarDecl>),
+    Expr(Expr)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    // Filled in by `typeck::check`; stays `None` for ill-typed expressions
+    pub ty: Option<Type>,
+    pub span: Span
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Plus,
+    Not,
+    BitNot,
+    Deref,
+    AddrOf,
+    PreInc,
+    PreDec,
+    PostInc,
+    PostDec
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Mod,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+    Ne,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+    Comma
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Ident(String, Option<SymbolId>),
+    Int(usize, IntSuffix),
+    Float(f64),
+    Str(String),
+    Char(char),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    // `a = b`, or `a op= b` when the operator is set
+    Assign(Option<BinaryOp>, Box<Expr>, Box<Expr>),
+    // `cond ? then : otherwise`
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    Index(Box<Expr>, Box<Expr>),
+    // `a.field`
+    Member(Box<Expr>, String),
+    // `a->field`
+    Arrow(Box<Expr>, String),
+    SizeofExpr(Box<Expr>),
+    SizeofType(Type),
+    Cast(Type, Box<Expr>)
+}
+
+impl Initializer {
+    pub fn span(&self) -> Span {
+        match self {
+            Initializer::Expr(expr) => expr.span,
+            Initializer::List(_, span) => *span
+        }
+    }
+}
+
+impl UnaryOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "-",
+            UnaryOp::Plus => "+",
+            UnaryOp::Not => "!",
+            UnaryOp::BitNot => "~",
+            UnaryOp::Deref => "*",
+            UnaryOp::AddrOf => "&",
+            UnaryOp::PreInc | UnaryOp::PostInc => "++",
+            UnaryOp::PreDec | UnaryOp::PostDec => "--"
+        }
+    }
+}
+
+impl BinaryOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Mod => "%",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Shl => "<<",
+            BinaryOp::Shr => ">>",
+            BinaryOp::Lt => "<",
+            BinaryOp::Gt => ">",
+            BinaryOp::Le => "<=",
+            BinaryOp::Ge => ">=",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitXor => "^",
+            BinaryOp::BitOr => "|",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+            BinaryOp::Comma => ","
+        }
+    }
+
+    // Binding strength, from 0 for `,` to 10 for `*`
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Comma => 0,
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::BitOr => 3,
+            BinaryOp::BitXor => 4,
+            BinaryOp::BitAnd => 5,
+            BinaryOp::Eq | BinaryOp::Ne => 6,
+            BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Le | BinaryOp::Ge => 7,
+            BinaryOp::Shl | BinaryOp::Shr => 8,
+            BinaryOp::Add | BinaryOp::Sub => 9,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => 10
+        }
+    }
+}
+
+impl Type {
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Type::Int { .. })
+    }
+
+    pub fn is_arithmetic(&self) -> bool {
+        matches!(self, Type::Int { .. } | Type::Float | Type::Double)
+    }
+
+    pub fn is_scalar(&self) -> bool {
+        self.is_arithmetic() || matches!(self, Type::Pointer(_))
+    }
+
+    // Type pointed to by a pointer or array
+    pub fn pointee(&self) -> Option<&Type> {
+        match self {
+            Type::Pointer(ty) | Type::Array(ty, _) => Some(ty),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Void => write!(f, "void"),
+            Type::Int { kind, unsigned } => {
+                if *unsigned { write!(f, "unsigned ")? }
+                write!(f, "{}", match kind {
+                    IntKind::Char => "char",
+                    IntKind::Short => "short",
+                    IntKind::Int => "int",
+                    IntKind::Long => "long"
+                })
+            },
+            Type::Float => write!(f, "float"),
+            Type::Double => write!(f, "double"),
+            Type::Struct(name) => write!(f, "struct {}", name),
+            Type::Pointer(ty) if matches!(**ty, Type::Pointer(_)) => write!(f, "{}*", ty),
+            Type::Pointer(ty) => write!(f, "{} *", ty),
+            Type::Array(ty, Some(len)) => write!(f, "{}[{}]", ty, len),
+            Type::Array(ty, None) => write!(f, "{}[]", ty),
+            Type::Function { return_type, params, variadic } => {
+                write!(f, "{}(", return_type)?;
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 { write!(f, ", ")? }
+                    write!(f, "{}", param)?;
+                }
+                match (params.is_empty(), variadic) {
+                    (true, false) => write!(f, "void)"),
+                    (true, true) => write!(f, "...)"),
+                    (false, true) => write!(f, ", ...)"),
+                    (false, false) => write!(f, ")")
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use crate::assembler::{Arg, Instruction, Label, Program, REG_FLAGS, REG_IP, REG_SP};
+
+pub fn r(reg: u16) -> Arg {
+    Arg::Reg(reg)
+}
+
+pub fn imm(value: u32) -> Arg {
+    Arg::Imm(value)
+}
+
+// Reference to a label, as written `.name` in assembly
+pub fn target(name: &str) -> Arg {
+    Arg::Label(name.to_string())
+}
+
+pub fn ip() -> Arg {
+    Arg::Reg(REG_IP)
+}
+
+pub fn sp() -> Arg {
+    Arg::Reg(REG_SP)
+}
+
+pub fn flgs() -> Arg {
+    Arg::Reg(REG_FLAGS)
+}
+
+/// Builds a `Program` without going through the text parser:
+///
+/// ```
+/// use compiler::builder::{imm, r, target, ProgramBuilder};
+///
+/// let program = ProgramBuilder::new()
+///     .label("main").mov(r(0), imm(0))
+///     .label("loop").inc(r(0)).cmp(r(0), imm(5)).blt(target("loop"))
+///     .build();
+/// ```
+pub struct ProgramBuilder {
+    program: Program
+}
+
+impl ProgramBuilder {
+    pub fn new() -> Self {
+        Self { program: Program::new() }
+    }
+
+    pub fn label(mut self, name: &str) -> Self {
+        self.program.labels.push(Label { name: name.to_string(), instructions: vec![] });
+        self
+    }
+
+    pub fn insn(mut self, insn: Instruction) -> Self {
+        match self.program.labels.last_mut() {
+            Some(label) => label.instructions.push(insn),
+            None => panic!("ERROR: {} added before any label", insn)
+        }
+        self
+    }
+
+    pub fn entry(mut self, name: &str) -> Self {
+        self.program.entry = Some(name.to_string());
+        self
+    }
+
+    pub fn stack(mut self, stack: u32) -> Self {
+        self.program.stack = stack;
+        self
+    }
+
+    // Appends values to the data section
+    pub fn word(mut self, values: &[u32]) -> Self {
+        self.program.data.extend_from_slice(values);
+        self
+    }
+
+    // Instruction with the given source mnemonic, e.g. `op("blt", vec![target("loop")])`
+    pub fn op(self, mnemonic: &str, args: Vec<Arg>) -> Self {
+        match Instruction::new(mnemonic, args) {
+            Some(insn) => self.insn(insn),
+            None => panic!("ERROR: no instruction {} with those operands", mnemonic)
+        }
+    }
+
+    pub fn mov(self, dest: Arg, src: Arg) -> Self { self.op("mov", vec![dest, src]) }
+    pub fn cmp(self, arg1: Arg, arg2: Arg) -> Self { self.op("cmp", vec![arg1, arg2]) }
+    pub fn b(self, target: Arg) -> Self { self.op("b", vec![target]) }
+    pub fn blt(self, target: Arg) -> Self { self.op("blt", vec![target]) }
+    pub fn bgt(self, target: Arg) -> Self { self.op("bgt", vec![target]) }
+    pub fn beq(self, target: Arg) -> Self { self.op("beq", vec![target]) }
+    pub fn bneq(self, target: Arg) -> Self { self.op("bneq", vec![target]) }
+    pub fn call(self, target: Arg) -> Self { self.op("call", vec![target]) }
+    pub fn calljs(self, function: Arg) -> Self { self.op("calljs", vec![function]) }
+    pub fn push(self, arg: Arg) -> Self { self.op("push", vec![arg]) }
+    pub fn str(self, dest: Arg, src: Arg) -> Self { self.op("str", vec![dest, src]) }
+    pub fn add(self, dest: Arg, arg1: Arg, arg2: Arg) -> Self { self.op("add", vec![dest, arg1, arg2]) }
+    pub fn inc(self, arg: Arg) -> Self { self.op("inc", vec![arg]) }
+    pub fn dec(self, arg: Arg) -> Self { self.op("dec", vec![arg]) }
+    pub fn cli(self) -> Self { self.op("cli", vec![]) }
+
+    pub fn build(self) -> Program {
+        self.program
+    }
+}
+
+impl Default for ProgramBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
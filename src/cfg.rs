@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+
+use crate::{assembler::{Arg, Instruction, Program, REG_SP}, diagnostic::{Diagnostic, Severity}, isa::PUSH_SIZE};
+
+pub struct Block {
+    // Range of instructions in program order, end exclusive
+    pub start: usize,
+    pub end: usize,
+    pub successors: Vec<usize>,
+    pub falls_off_end: bool
+}
+
+pub struct Cfg<'a> {
+    pub program: &'a Program,
+    pub blocks: Vec<Block>,
+    // (label index, instruction index) of every instruction in program order
+    locations: Vec<(usize, usize)>,
+    label_starts: HashMap<&'a str, usize>,
+    indirect: bool
+}
+
+impl<'a> Cfg<'a> {
+    pub fn build(program: &'a Program) -> Self {
+        let mut locations = vec![];
+        let mut label_starts = HashMap::new();
+        for (label_index, label) in program.labels.iter().enumerate() {
+            label_starts.insert(label.name.as_str(), locations.len());
+            for index in 0..label.instructions.len() {
+                locations.push((label_index, index));
+            }
+        }
+
+        let mut cfg = Self { program, blocks: vec![], locations, label_starts, indirect: false };
+
+        let len = cfg.locations.len();
+        let mut leaders = vec![false; len + 1];
+        leaders[0] = true;
+        leaders[len] = true;
+        for start in cfg.label_starts.values() {
+            leaders[*start] = true;
+        }
+        for pos in 0..len {
+            match cfg.operation(pos) {
+                ("b", _) => leaders[pos + 1] = true,
+                ("call", [Arg::Reg(_)]) => cfg.indirect = true,
+                _ => {}
+            }
+        }
+
+        let mut block_of = vec![0usize; len + 1];
+        let mut start = 0;
+        for pos in 1..=len {
+            if leaders[pos] {
+                cfg.blocks.push(Block { start, end: pos, successors: vec![], falls_off_end: false });
+                start = pos;
+            }
+            block_of[pos] = cfg.blocks.len();
+        }
+
+        for id in 0..cfg.blocks.len() {
+            let last = cfg.blocks[id].end - 1;
+            let mut targets = vec![];
+            let mut falls_through = true;
+            let insn = cfg.instruction(last);
+            if insn.mnemonic == "b" {
+                match insn.args.as_slice() {
+                    [Arg::Label(target)] => targets.extend(cfg.label_starts.get(target.as_str()).copied()),
+                    _ => cfg.indirect = true
+                }
+                falls_through = insn.cond.is_some();
+            }
+            if falls_through { targets.push(last + 1) }
+
+            for target in targets {
+                if target == len {
+                    cfg.blocks[id].falls_off_end = true;
+                } else if !cfg.blocks[id].successors.contains(&block_of[target]) {
+                    cfg.blocks[id].successors.push(block_of[target]);
+                }
+            }
+        }
+
+        cfg
+    }
+
+    fn instruction(&self, pos: usize) -> &'a Instruction {
+        let (label, index) = self.locations[pos];
+        &self.program.labels[label].instructions[index]
+    }
+
+    // Base mnemonic and operands, for matching on
+    fn operation(&self, pos: usize) -> (&'static str, &'a [Arg]) {
+        let insn = self.instruction(pos);
+        (insn.mnemonic, insn.args.as_slice())
+    }
+
+    fn diagnostic(&self, severity: Severity, pos: usize, message: String) -> Diagnostic {
+        let (label, index) = self.locations[pos];
+        Diagnostic::at_instruction(severity, message, &self.program.labels[label].name, index)
+    }
+
+    fn block_at(&self, pos: usize) -> Option<usize> {
+        self.blocks.iter().position(|block| block.start == pos)
+    }
+
+    // Entry block plus every direct call target. With indirect control flow any label may be
+    // jumped to, so every label start becomes a root as well.
+    fn roots(&self) -> Vec<usize> {
+        let mut roots = vec![];
+        let entry = match &self.program.entry {
+            Some(entry) => self.label_starts.get(entry.as_str()).copied(),
+            None => Some(0)
+        };
+        roots.extend(entry.and_then(|start| self.block_at(start)));
+
+        for pos in 0..self.locations.len() {
+            if let ("call", [Arg::Label(target)]) = self.operation(pos) {
+                if let Some(block) = self.label_starts.get(target.as_str()).and_then(|start| self.block_at(*start)) {
+                    roots.push(block);
+                }
+            }
+        }
+
+        if self.indirect {
+            roots.extend(self.label_starts.values().filter_map(|start| self.block_at(*start)));
+        }
+
+        roots
+    }
+
+    fn check_targets(&self, diagnostics: &mut Vec<Diagnostic>) {
+        if let Some(entry) = &self.program.entry {
+            if !self.label_starts.contains_key(entry.as_str()) && !self.locations.is_empty() {
+                diagnostics.push(self.diagnostic(Severity::Error, 0, format!("entry label .{} is not defined", entry)));
+            }
+        }
+
+        for pos in 0..self.locations.len() {
+            match self.operation(pos) {
+                ("b", [Arg::Label(target)]) if !self.label_starts.contains_key(target.as_str()) => {
+                    diagnostics.push(self.diagnostic(Severity::Error, pos, format!("branch to undefined label .{}", target)));
+                },
+                ("call", [Arg::Label(target)]) if !self.label_starts.contains_key(target.as_str()) => {
+                    diagnostics.push(self.diagnostic(Severity::Error, pos, format!("call to undefined label .{}", target)));
+                },
+                ("call", [Arg::Imm(address)]) => {
+                    diagnostics.push(self.diagnostic(Severity::Warning, pos, format!("call target {} is not a label", address)));
+                },
+                _ => {}
+            }
+        }
+    }
+
+    fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut worklist = self.roots();
+        while let Some(id) = worklist.pop() {
+            if reachable[id] { continue }
+            reachable[id] = true;
+            worklist.extend(self.blocks[id].successors.iter().copied());
+        }
+
+        reachable
+    }
+
+    // Tracks how many bytes are pushed on the stack relative to the start of each root.
+    // Paths through instructions that set `sp` in any other way are not followed.
+    fn check_stack(&self, diagnostics: &mut Vec<Diagnostic>) {
+        let mut depths: Vec<Option<i64>> = vec![None; self.blocks.len()];
+        let mut worklist = vec![];
+        for root in self.roots() {
+            if depths[root].is_none() {
+                depths[root] = Some(0);
+                worklist.push(root);
+            }
+        }
+
+        while let Some(id) = worklist.pop() {
+            let block = &self.blocks[id];
+            let mut depth = depths[id].unwrap();
+            let mut known = true;
+
+            for pos in block.start..block.end {
+                match self.operation(pos) {
+                    ("push", _) => depth += PUSH_SIZE as i64,
+                    ("add", [Arg::Reg(REG_SP), Arg::Reg(REG_SP), Arg::Imm(value)]) => {
+                        depth -= *value as i64;
+                        if depth < 0 {
+                            diagnostics.push(self.diagnostic(Severity::Warning, pos,
+                                format!("stack pointer moved {} bytes above the pushed values", -depth)));
+                            known = false;
+                            break;
+                        }
+                    },
+                    ("mov" | "add" | "inc" | "dec", [Arg::Reg(REG_SP), ..]) => {
+                        known = false;
+                        break;
+                    },
+                    _ => {}
+                }
+            }
+            if !known { continue }
+
+            if block.falls_off_end && depth != 0 {
+                diagnostics.push(self.diagnostic(Severity::Warning, block.end - 1,
+                    format!("{} bytes left on the stack at end of program", depth)));
+            }
+
+            for successor in &block.successors {
+                match depths[*successor] {
+                    None => {
+                        depths[*successor] = Some(depth);
+                        worklist.push(*successor);
+                    },
+                    Some(other) if other != depth => {
+                        diagnostics.push(self.diagnostic(Severity::Warning, self.blocks[*successor].start,
+                            format!("stack imbalance: reached with {} and {} bytes pushed", other, depth)));
+                    },
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    pub fn check(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        self.check_targets(&mut diagnostics);
+
+        let reachable = self.reachable();
+        for (id, block) in self.blocks.iter().enumerate() {
+            if !reachable[id] {
+                diagnostics.push(self.diagnostic(Severity::Warning, block.start, "unreachable code".to_string()));
+            } else if block.falls_off_end {
+                diagnostics.push(self.diagnostic(Severity::Warning, block.end - 1, "execution falls off the end of the program".to_string()));
+            }
+        }
+
+        self.check_stack(&mut diagnostics);
+        diagnostics
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut result = String::from("digraph cfg {\n\tnode [shape=box, fontname=\"monospace\"];\n");
+        for (id, block) in self.blocks.iter().enumerate() {
+            let (label, index) = self.locations[block.start];
+            let mut text = format!("{}+{}\\l", escape(&self.program.labels[label].name), index);
+            for pos in block.start..block.end {
+                text += &format!("{}\\l", escape(&self.instruction(pos).to_string()));
+            }
+            result += &format!("\tb{} [label=\"{}\"];\n", id, text);
+
+            for successor in &block.successors {
+                result += &format!("\tb{} -> b{};\n", id, successor);
+            }
+            if block.falls_off_end {
+                result += &format!("\tend [shape=point];\n\tb{} -> end;\n", id);
+            }
+        }
+        result += "}\n";
+
+        result
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
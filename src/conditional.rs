@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+
+use crate::{diagnostic::Diagnostic, lexer::{SourceMap, Span}};
+
+// Conditional assembly: `.define`, `.undef`, `.if`, `.ifdef`, `.ifndef`, `.elif`, `.else` and
+// `.endif` are evaluated line by line before parsing. Directive lines and lines in inactive
+// branches are replaced by empty lines, so line numbers are preserved. Defined constants can
+// also be used as operands, and are replaced with the value defined at that line. Malformed
+// directives are reported and otherwise ignored.
+
+struct Branch {
+    // Whether the enclosing block is being assembled
+    parent_active: bool,
+    // Whether one of the branches so far was taken
+    taken: bool,
+    active: bool,
+    seen_else: bool,
+    // The `.if` line, for reporting a missing `.endif`
+    span: Span
+}
+
+// Value of an expression operand. Names that aren't defined stand for themselves in
+// comparisons (`HOST == node`) but are false on their own.
+struct Value {
+    text: String,
+    defined: bool
+}
+
+impl Value {
+    fn is_true(&self) -> bool {
+        self.defined && !self.text.is_empty() && self.text != "0"
+    }
+
+    fn equals(&self, other: &Value) -> bool {
+        match (parse_number(&self.text), parse_number(&other.text)) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.text == other.text
+        }
+    }
+}
+
+fn parse_number(text: &str) -> Option<u64> {
+    if let Some(hex) = text.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok()
+    } else if let Some(oct) = text.strip_prefix("0o") {
+        u64::from_str_radix(oct, 8).ok()
+    } else if let Some(bin) = text.strip_prefix("0b") {
+        u64::from_str_radix(bin, 2).ok()
+    } else {
+        text.parse::<u64>().ok()
+    }
+}
+
+fn tokenize(expr: &str) -> Result<Vec<String>, String> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') { i += 1 }
+            tokens.push(chars[start..i].iter().collect());
+        } else if c == '"' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i] != '"' { i += 1 }
+            if i == chars.len() { return Err("unterminated string in condition".to_string()) }
+            i += 1;
+            tokens.push(chars[start..i].iter().collect());
+        } else {
+            let pair: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            if pair == "==" || pair == "!=" || pair == "&&" || pair == "||" {
+                tokens.push(pair);
+                i += 2;
+            } else if c == '!' || c == '(' || c == ')' {
+                tokens.push(c.to_string());
+                i += 1;
+            } else {
+                return Err(format!("unexpected '{}' in condition", c));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Evaluator<'a> {
+    tokens: Vec<String>,
+    pos: usize,
+    defines: &'a HashMap<String, String>
+}
+
+impl Evaluator<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|token| token.as_str())
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.clone())
+            },
+            None => Err("unexpected end of condition".to_string())
+        }
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        let token = self.next()?;
+        if token != expected { return Err(format!("expected '{}' in condition, found '{}'", expected, token)) }
+        Ok(())
+    }
+
+    fn or(&mut self) -> Result<bool, String> {
+        let mut result = self.and()?;
+        while self.peek() == Some("||") {
+            self.pos += 1;
+            result |= self.and()?;
+        }
+        Ok(result)
+    }
+
+    fn and(&mut self) -> Result<bool, String> {
+        let mut result = self.unary()?;
+        while self.peek() == Some("&&") {
+            self.pos += 1;
+            result &= self.unary()?;
+        }
+        Ok(result)
+    }
+
+    fn unary(&mut self) -> Result<bool, String> {
+        match self.peek() {
+            Some("!") => {
+                self.pos += 1;
+                Ok(!self.unary()?)
+            },
+            Some("(") => {
+                self.pos += 1;
+                let result = self.or()?;
+                self.expect(")")?;
+                Ok(result)
+            },
+            _ => self.comparison()
+        }
+    }
+
+    fn comparison(&mut self) -> Result<bool, String> {
+        let left = self.operand()?;
+        match self.peek() {
+            Some("==") => {
+                self.pos += 1;
+                Ok(left.equals(&self.operand()?))
+            },
+            Some("!=") => {
+                self.pos += 1;
+                Ok(!left.equals(&self.operand()?))
+            },
+            _ => Ok(left.is_true())
+        }
+    }
+
+    fn operand(&mut self) -> Result<Value, String> {
+        let token = self.next()?;
+        if token == "defined" {
+            self.expect("(")?;
+            let name = self.next()?;
+            self.expect(")")?;
+            let defined = self.defines.contains_key(&name);
+            return Ok(Value { text: if defined { "1" } else { "0" }.to_string(), defined: true });
+        }
+        if let Some(text) = token.strip_prefix('"') {
+            return Ok(Value { text: text.trim_end_matches('"').to_string(), defined: true });
+        }
+        if parse_number(&token).is_some() {
+            return Ok(Value { text: token, defined: true });
+        }
+
+        Ok(match self.defines.get(&token) {
+            Some(value) => Value { text: value.clone(), defined: true },
+            None => Value { text: token, defined: false }
+        })
+    }
+}
+
+fn evaluate(expr: &str, defines: &HashMap<String, String>) -> Result<bool, String> {
+    let mut evaluator = Evaluator { tokens: tokenize(expr)?, pos: 0, defines };
+    let result = evaluator.or()?;
+    if let Some(token) = evaluator.peek() {
+        return Err(format!("unexpected '{}' in condition", token));
+    }
+    Ok(result)
+}
+
+fn single_name(rest: &str, directive: &str) -> Result<String, String> {
+    let mut words = rest.split_whitespace();
+    match (words.next(), words.next()) {
+        (Some(name), None) => Ok(name.to_string()),
+        _ => Err(format!(".{} expects a single name", directive))
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+// Replaces the defined names among the operands of an instruction or directive. The first word
+// of the line and label references such as `.name` are left alone.
+fn substitute(text: &str, defines: &HashMap<String, String>) -> String {
+    let trimmed = text.trim_start();
+    let Some(first_end) = trimmed.find(char::is_whitespace) else { return text.to_string() };
+    let split = text.len() - trimmed.len() + first_end;
+    let mut result = text[..split].to_string();
+
+    let mut rest = &text[split..];
+    while let Some(start) = rest.find(is_word_char) {
+        let end = rest[start..].find(|c| !is_word_char(c)).map_or(rest.len(), |end| start + end);
+        let word = &rest[start..end];
+        result += &rest[..start];
+        match defines.get(word) {
+            Some(value) if !rest[..start].ends_with('.') => result += value,
+            _ => result += word
+        }
+        rest = &rest[end..];
+    }
+    result += rest;
+
+    result
+}
+
+// The source with conditional directives applied, and the problems found in them
+pub fn preprocess(source: &str, defines: &mut HashMap<String, String>) -> (String, Vec<Diagnostic>) {
+    let source_map = SourceMap::new(source);
+    let mut branches: Vec<Branch> = vec![];
+    let mut diagnostics = vec![];
+    let mut result = String::new();
+    let mut offset = 0;
+
+    for (index, text) in source.split('\n').enumerate() {
+        if index > 0 { result.push('\n') }
+        let line_start = offset;
+        offset += text.len() + 1;
+
+        let active = branches.last().is_none_or(|branch| branch.active);
+        let trimmed = text.trim();
+        let (directive, rest) = match trimmed.split_once(char::is_whitespace) {
+            Some((directive, rest)) => (directive, rest.trim()),
+            None => (trimmed, "")
+        };
+        let trimmed_start = line_start + text.len() - text.trim_start().len();
+        let span = source_map.span(trimmed_start, trimmed_start + trimmed.len());
+        let mut error = |message: String| diagnostics.push(Diagnostic::error(message, span));
+
+        match directive {
+            ".if" | ".ifdef" | ".ifndef" => {
+                let condition = active && match directive {
+                    ".ifdef" => single_name(rest, "ifdef").map(|name| defines.contains_key(&name)),
+                    ".ifndef" => single_name(rest, "ifndef").map(|name| !defines.contains_key(&name)),
+                    _ => evaluate(rest, defines)
+                }.unwrap_or_else(|message| {
+                    error(message);
+                    false
+                });
+                branches.push(Branch { parent_active: active, taken: condition, active: condition, seen_else: false, span });
+            },
+            ".elif" => match branches.last_mut() {
+                Some(branch) if !branch.seen_else => {
+                    let condition = branch.parent_active && !branch.taken && evaluate(rest, defines).unwrap_or_else(|message| {
+                        error(message);
+                        false
+                    });
+                    branch.active = condition;
+                    branch.taken |= condition;
+                },
+                Some(_) => error(".elif after .else".to_string()),
+                None => error(".elif without .if".to_string())
+            },
+            ".else" => match branches.last_mut() {
+                Some(branch) if !branch.seen_else => {
+                    branch.active = branch.parent_active && !branch.taken;
+                    branch.taken = true;
+                    branch.seen_else = true;
+                },
+                Some(_) => error("duplicate .else".to_string()),
+                None => error(".else without .if".to_string())
+            },
+            ".endif" => {
+                if branches.pop().is_none() { error(".endif without .if".to_string()) }
+            },
+            ".define" if active => {
+                let (name, value) = match rest.split_once(char::is_whitespace) {
+                    Some((name, value)) => (name, value.trim()),
+                    None => (rest, "1")
+                };
+                if name.is_empty() {
+                    error(".define expects a name".to_string());
+                } else {
+                    defines.insert(name.to_string(), value.to_string());
+                }
+            },
+            ".undef" if active => match single_name(rest, "undef") {
+                Ok(name) => { defines.remove(&name); },
+                Err(message) => error(message)
+            },
+            _ => if active { result += &substitute(text, defines) }
+        }
+    }
+
+    for branch in branches {
+        diagnostics.push(Diagnostic::error(".if without .endif".to_string(), branch.span));
+    }
+
+    (result, diagnostics)
+}
@@ -0,0 +1,78 @@
+use std::fmt;
+
+use crate::lexer::{SourceMap, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Warning,
+    Error
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Location {
+    Span(Span),
+    // Instruction `index` after `.label`, for assembly that may have been built or rewritten
+    // without any source to point into
+    Instruction { label: String, index: usize }
+}
+
+// Problem found in C or assembly source, or in an assembled program
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub location: Location
+}
+
+impl Diagnostic {
+    pub fn error(message: String, span: Span) -> Self {
+        Self { severity: Severity::Error, message, location: Location::Span(span) }
+    }
+
+    pub fn warning(message: String, span: Span) -> Self {
+        Self { severity: Severity::Warning, message, location: Location::Span(span) }
+    }
+
+    pub fn at_instruction(severity: Severity, message: String, label: &str, index: usize) -> Self {
+        Self { severity, message, location: Location::Instruction { label: label.to_string(), index } }
+    }
+
+    pub fn span(&self) -> Option<&Span> {
+        match &self.location {
+            Location::Span(span) => Some(span),
+            Location::Instruction { .. } => None
+        }
+    }
+
+    // The message followed by the source line with the span underlined, if there is one
+    pub fn render(&self, source_map: &SourceMap) -> String {
+        match self.span() {
+            Some(span) => format!("{}\n{}", self, source_map.snippet(span)),
+            None => format!("{}\n", self)
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error")
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Span(span) => write!(f, "{}", span),
+            Location::Instruction { label, index } => write!(f, ".{}+{}", label, index)
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.location, self.severity, self.message)
+    }
+}
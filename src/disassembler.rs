@@ -0,0 +1,54 @@
+use crate::{assembler::{REG_FLAGS, REG_IP, REG_SP}, isa::{self, Operand, REG_COUNT}};
+
+fn register_name(reg: u32) -> Option<String> {
+    if reg >= REG_COUNT { return None }
+
+    Some(match reg as u16 {
+        REG_IP => "ip".to_string(),
+        REG_SP => "sp".to_string(),
+        REG_FLAGS => "flgs".to_string(),
+        _ => format!("r{}", reg)
+    })
+}
+
+// Turns a numeric image produced by `Program::encode` back into assembly, one instruction
+// per line prefixed with its word offset
+pub fn disassemble(words: &[u32]) -> Result<String, String> {
+    let mut result = String::new();
+    let mut offset = 0;
+
+    while offset < words.len() {
+        let spec = match isa::op_by_opcode(words[offset]) {
+            Some(spec) => spec,
+            None => return Err(format!("unknown opcode {} at offset {}", words[offset], offset))
+        };
+        if offset + spec.size() > words.len() {
+            return Err(format!("{} at offset {} runs past the end of the program", spec.name, offset));
+        }
+
+        let mut mnemonic = spec.mnemonic.to_string();
+        let mut args = vec![];
+        for (operand, word) in spec.operands.iter().zip(&words[offset + 1..offset + spec.size()]) {
+            match operand {
+                Operand::Condition => match isa::cond_by_value(*word) {
+                    Some(cond) => mnemonic += cond.suffix,
+                    None => return Err(format!("unknown branch condition {} at offset {}", word, offset))
+                },
+                Operand::Reg => match register_name(*word) {
+                    Some(name) => args.push(name),
+                    None => return Err(format!("register {} out of range at offset {}", word, offset))
+                },
+                Operand::Imm | Operand::Addr => args.push(word.to_string())
+            }
+        }
+
+        if !args.is_empty() {
+            mnemonic += " ";
+            mnemonic += &args.join(", ");
+        }
+        result += &format!("{:>5}: {}\n", offset, mnemonic);
+        offset += spec.size();
+    }
+
+    Ok(result)
+}
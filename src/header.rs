@@ -0,0 +1,59 @@
+// Program header emitted in front of the numeric image:
+//
+//     [MAGIC, FORMAT_VERSION, entry, sp, code size, data size] code... data...
+//
+// Code addresses (branch and call targets, the entry offset) are word offsets from the start
+// of the code, not from the start of the image.
+
+pub const MAGIC: u32 = 0x534d4a56; // "VJMS"
+pub const FORMAT_VERSION: u32 = 1;
+pub const HEADER_SIZE: usize = 6;
+pub const DEFAULT_STACK: u32 = 0x8000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub version: u32,
+    pub entry: u32,
+    pub stack: u32,
+    pub code_size: u32,
+    pub data_size: u32
+}
+
+impl Header {
+    pub fn to_words(&self) -> Vec<u32> {
+        vec![MAGIC, self.version, self.entry, self.stack, self.code_size, self.data_size]
+    }
+
+    // Reads the header at the start of `words` and checks it against the image
+    pub fn from_words(words: &[u32]) -> Result<Header, String> {
+        if words.len() < HEADER_SIZE {
+            return Err(format!("image is {} words long, shorter than the header", words.len()));
+        }
+        if words[0] != MAGIC {
+            return Err(format!("bad magic number {:#x}", words[0]));
+        }
+        if words[1] != FORMAT_VERSION {
+            return Err(format!("unsupported format version {} (expected {})", words[1], FORMAT_VERSION));
+        }
+
+        let header = Header { version: words[1], entry: words[2], stack: words[3], code_size: words[4], data_size: words[5] };
+        let expected = HEADER_SIZE + header.code_size as usize + header.data_size as usize;
+        if words.len() != expected {
+            return Err(format!("image is {} words long, header describes {}", words.len(), expected));
+        }
+        if header.code_size > 0 && header.entry >= header.code_size {
+            return Err(format!("entry offset {} is outside of the code", header.entry));
+        }
+
+        Ok(header)
+    }
+}
+
+// Code of an image read back from the output. Images starting with the magic number must have
+// a valid header, anything else is taken to be bare code.
+pub fn code_of(words: &[u32]) -> Result<&[u32], String> {
+    if words.first() != Some(&MAGIC) { return Ok(words) }
+
+    let header = Header::from_words(words)?;
+    Ok(&words[HEADER_SIZE..HEADER_SIZE + header.code_size as usize])
+}
@@ -0,0 +1,160 @@
+use crate::assembler::{Arg, Cond};
+
+// General purpose registers followed by ip, sp and flgs
+pub const REG_COUNT: u32 = 128;
+
+// Bytes `push` moves `sp` by
+pub const PUSH_SIZE: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    Reg,
+    Imm,
+    // Code address: a label or an absolute word offset
+    Addr,
+    // Branch condition, encoded as a `BranchType` value
+    Condition
+}
+
+pub struct OpSpec {
+    // Name of the `Op` enum member in the VM
+    pub name: &'static str,
+    pub opcode: u32,
+    pub mnemonic: &'static str,
+    pub operands: &'static [Operand]
+}
+
+pub struct CondSpec {
+    pub cond: Cond,
+    // Name of the `BranchType` enum member in the VM
+    pub name: &'static str,
+    // Appended to `b` to form the mnemonic, e.g. `blt`
+    pub suffix: &'static str,
+    pub value: u32
+}
+
+use Operand::*;
+
+pub const OPS: &[OpSpec] = &[
+    OpSpec { name: "MOV_CONST",           opcode: 0,  mnemonic: "mov",    operands: &[Reg, Imm] },
+    OpSpec { name: "MOV_REG",             opcode: 1,  mnemonic: "mov",    operands: &[Reg, Reg] },
+    OpSpec { name: "CMP_REG_CONST",       opcode: 2,  mnemonic: "cmp",    operands: &[Reg, Imm] },
+    OpSpec { name: "CMP_REG_REG",         opcode: 3,  mnemonic: "cmp",    operands: &[Reg, Reg] },
+    OpSpec { name: "BRANCH_CONST",        opcode: 4,  mnemonic: "b",      operands: &[Addr] },
+    OpSpec { name: "BRANCH_REG",          opcode: 5,  mnemonic: "b",      operands: &[Reg] },
+    OpSpec { name: "BRANCH_COND_CONST",   opcode: 6,  mnemonic: "b",      operands: &[Condition, Addr] },
+    OpSpec { name: "BRANCH_COND_REG",     opcode: 7,  mnemonic: "b",      operands: &[Condition, Reg] },
+    OpSpec { name: "CALL_CONST",          opcode: 8,  mnemonic: "call",   operands: &[Addr] },
+    OpSpec { name: "CALL_REG",            opcode: 9,  mnemonic: "call",   operands: &[Reg] },
+    OpSpec { name: "CALL_JS_CONST",       opcode: 10, mnemonic: "calljs", operands: &[Imm] },
+    OpSpec { name: "CALL_JS_REG",         opcode: 11, mnemonic: "calljs", operands: &[Reg] },
+    OpSpec { name: "PUSH_CONST",          opcode: 12, mnemonic: "push",   operands: &[Imm] },
+    OpSpec { name: "PUSH_REG",            opcode: 13, mnemonic: "push",   operands: &[Reg] },
+    OpSpec { name: "STR_CONST_TO_CONST",  opcode: 14, mnemonic: "str",    operands: &[Imm, Imm] },
+    OpSpec { name: "STR_CONST_TO_REG",    opcode: 15, mnemonic: "str",    operands: &[Reg, Imm] },
+    OpSpec { name: "STR_REG_TO_CONST",    opcode: 16, mnemonic: "str",    operands: &[Imm, Reg] },
+    OpSpec { name: "STR_REG_TO_REG",      opcode: 17, mnemonic: "str",    operands: &[Reg, Reg] },
+    OpSpec { name: "ADD_CONST",           opcode: 18, mnemonic: "add",    operands: &[Reg, Reg, Imm] },
+    OpSpec { name: "ADD_REG",             opcode: 19, mnemonic: "add",    operands: &[Reg, Reg, Reg] },
+    OpSpec { name: "INC",                 opcode: 20, mnemonic: "inc",    operands: &[Reg] },
+    OpSpec { name: "DEC",                 opcode: 21, mnemonic: "dec",    operands: &[Reg] },
+    OpSpec { name: "CLEAR_FLAGS",         opcode: 22, mnemonic: "cli",    operands: &[] },
+];
+
+pub const CONDS: &[CondSpec] = &[
+    CondSpec { cond: Cond::LT,  name: "LT",  suffix: "lt",  value: 0 },
+    CondSpec { cond: Cond::GT,  name: "GT",  suffix: "gt",  value: 1 },
+    CondSpec { cond: Cond::EQ,  name: "EQ",  suffix: "eq",  value: 2 },
+    CondSpec { cond: Cond::NEQ, name: "NEQ", suffix: "neq", value: 3 },
+];
+
+impl OpSpec {
+    // Words taken by the instruction: the opcode followed by its operands
+    pub fn size(&self) -> usize {
+        1 + self.operands.len()
+    }
+
+    // Operands written in assembly; the condition is part of the mnemonic
+    pub fn arity(&self) -> usize {
+        self.operands.iter().filter(|operand| **operand != Condition).count()
+    }
+
+    pub fn is_conditional(&self) -> bool {
+        self.operands.first() == Some(&Condition)
+    }
+}
+
+impl Operand {
+    pub fn accepts(&self, arg: &Arg) -> bool {
+        matches!(
+            (self, arg),
+            (Reg, Arg::Reg(_)) | (Imm, Arg::Imm(_)) | (Addr, Arg::Imm(_)) | (Addr, Arg::Label(_))
+        )
+    }
+}
+
+pub fn op_by_opcode(opcode: u32) -> Option<&'static OpSpec> {
+    OPS.iter().find(|op| op.opcode == opcode)
+}
+
+pub fn op_by_name(name: &str) -> Option<&'static OpSpec> {
+    OPS.iter().find(|op| op.name == name)
+}
+
+pub fn cond_spec(cond: Cond) -> &'static CondSpec {
+    CONDS.iter().find(|spec| spec.cond == cond).unwrap()
+}
+
+pub fn cond_by_value(value: u32) -> Option<&'static CondSpec> {
+    CONDS.iter().find(|spec| spec.value == value)
+}
+
+pub fn cond_by_name(name: &str) -> Option<&'static CondSpec> {
+    CONDS.iter().find(|spec| spec.name == name)
+}
+
+// Splits a source mnemonic such as `blt` into its base mnemonic and condition
+pub fn split_mnemonic(mnemonic: &str) -> Option<(&str, Option<&'static CondSpec>)> {
+    if OPS.iter().any(|op| op.mnemonic == mnemonic && !op.is_conditional()) {
+        return Some((mnemonic, None));
+    }
+
+    CONDS.iter().find_map(|spec| {
+        let base = mnemonic.strip_suffix(spec.suffix)?;
+        OPS.iter().find(|op| op.mnemonic == base && op.is_conditional()).map(|_| (base, Some(spec)))
+    })
+}
+
+pub fn arity(mnemonic: &str) -> Option<usize> {
+    let (base, cond) = split_mnemonic(mnemonic)?;
+    OPS.iter()
+        .find(|op| op.mnemonic == base && op.is_conditional() == cond.is_some())
+        .map(|op| op.arity())
+}
+
+// Picks the encoding of `mnemonic` whose operand kinds accept `args`
+pub fn select(mnemonic: &str, conditional: bool, args: &[&Arg]) -> Option<&'static OpSpec> {
+    OPS.iter().find(|op| {
+        let operands = if op.is_conditional() { &op.operands[1..] } else { op.operands };
+        op.mnemonic == mnemonic && op.is_conditional() == conditional &&
+            operands.len() == args.len() &&
+            operands.iter().zip(args).all(|(operand, arg)| operand.accepts(arg))
+    })
+}
+
+// JS module with the `Op` and `BranchType` enums used by the VM
+pub fn js_enums() -> String {
+    let mut result = String::from("// Generated by the assembler from src/isa.rs. Do not edit.\n\n");
+
+    result += "export const Op = Object.freeze({\n";
+    for op in OPS {
+        result += &format!("    {}: {},\n", op.name, op.opcode);
+    }
+    result += "});\n\nexport const BranchType = Object.freeze({\n";
+    for spec in CONDS {
+        result += &format!("    {}: {},\n", spec.name, spec.value);
+    }
+    result += "});\n";
+
+    result
+}
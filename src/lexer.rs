@@ -1,144 +1,647 @@
-use std::{fmt, fs::canonicalize};
+use std::{collections::VecDeque, fmt, str::FromStr};
 
-#[derive(Debug, PartialEq)]
+use crate::diagnostic::Diagnostic;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     Symbol(char),
-    Id(String), // if, else
-    Num(usize), // 
+    Id(String),
+    Keyword(Keyword),
+    Num(usize, IntSuffix),
     Float(f64),
+    Str(String),
+    Char(char),
     Eq(),
     And(),
-    Or()
+    Or(),
+
+    Plus(), Minus(), Star(), Slash(), Percent(),
+    Inc(), Dec(),
+    Assign(),
+    PlusAssign(), MinusAssign(), StarAssign(), SlashAssign(), PercentAssign(),
+    AmpAssign(), PipeAssign(), CaretAssign(), ShlAssign(), ShrAssign(),
+    Ne(), Lt(), Gt(), Le(), Ge(),
+    Not(), Tilde(), Amp(), Pipe(), Caret(), Shl(), Shr(),
+    Arrow(), Dot(), Comma(), Question(), Colon(), Ellipsis()
 }
 
-pub struct Lexer {
-    pub tokens: Vec<Token>,
-    pub program: String,
-    pub tok: usize,
-    pub current_id: String
+// `u` and `l`/`ll` suffixes of an integer literal
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct IntSuffix {
+    pub unsigned: bool,
+    pub long: bool
 }
 
-impl Lexer {
-    pub fn new() -> Self {
-        Self { 
-            tokens: vec![],
-            program: String::new(),
-            current_id: String::new(),
-            tok: 0,
-        }
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Keyword {
+    Auto, Break, Case, Char, Const, Continue, Default, Do, Double, Else, Enum, Extern,
+    Float, For, Goto, If, Inline, Int, Long, Register, Restrict, Return, Short, Signed,
+    Sizeof, Static, Struct, Switch, Typedef, Union, Unsigned, Void, Volatile, While
+}
+
+const KEYWORDS: [(&str, Keyword); 34] = [
+    ("auto", Keyword::Auto), ("break", Keyword::Break), ("case", Keyword::Case),
+    ("char", Keyword::Char), ("const", Keyword::Const), ("continue", Keyword::Continue),
+    ("default", Keyword::Default), ("do", Keyword::Do), ("double", Keyword::Double),
+    ("else", Keyword::Else), ("enum", Keyword::Enum), ("extern", Keyword::Extern),
+    ("float", Keyword::Float), ("for", Keyword::For), ("goto", Keyword::Goto),
+    ("if", Keyword::If), ("inline", Keyword::Inline), ("int", Keyword::Int),
+    ("long", Keyword::Long), ("register", Keyword::Register), ("restrict", Keyword::Restrict),
+    ("return", Keyword::Return), ("short", Keyword::Short), ("signed", Keyword::Signed),
+    ("sizeof", Keyword::Sizeof), ("static", Keyword::Static), ("struct", Keyword::Struct),
+    ("switch", Keyword::Switch), ("typedef", Keyword::Typedef), ("union", Keyword::Union),
+    ("unsigned", Keyword::Unsigned), ("void", Keyword::Void), ("volatile", Keyword::Volatile),
+    ("while", Keyword::While)
+];
+
+impl Keyword {
+    pub fn as_str(&self) -> &'static str {
+        KEYWORDS.iter().find(|(_, keyword)| keyword == self).unwrap().0
     }
+}
 
-    fn skip_until(&mut self, until: char) -> String {
-        let mut c = self.program.chars().nth(self.tok).unwrap();
-        let mut result = String::new();
-        while c != until {
-            result.push(c);
-            self.tok += 1;
+impl FromStr for Keyword {
+    type Err = ();
 
-            if self.tok < self.program.len() {
-                c = self.program.chars().nth(self.tok).unwrap();
-            } else {
-                break;
-            }
-        }
+    fn from_str(text: &str) -> Result<Keyword, ()> {
+        KEYWORDS.iter().find(|(name, _)| *name == text).map(|(_, keyword)| *keyword).ok_or(())
+    }
+}
 
-        result
+impl fmt::Display for Keyword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
     }
+}
 
-    fn skip_until_whitespace(&mut self) -> String {
-        let mut c = self.program.chars().nth(self.tok).unwrap();
-        let mut result = String::new();
-        while c != ' ' && c != '\t' && c != '\n' && self.tok < self.program.len()  {
-            result.push(c);
-            self.tok += 1;
-            if self.tok < self.program.len() {
-                c = self.program.chars().nth(self.tok).unwrap();
-            } else {
-                break;
-            }
-        }
+impl Token {
+    // Source text of an operator or punctuator
+    pub fn spelling(&self) -> Option<&'static str> {
+        let text = match self {
+            Token::Eq() => "==", Token::And() => "&&", Token::Or() => "||",
+            Token::Plus() => "+", Token::Minus() => "-", Token::Star() => "*",
+            Token::Slash() => "/", Token::Percent() => "%",
+            Token::Inc() => "++", Token::Dec() => "--",
+            Token::Assign() => "=",
+            Token::PlusAssign() => "+=", Token::MinusAssign() => "-=", Token::StarAssign() => "*=",
+            Token::SlashAssign() => "/=", Token::PercentAssign() => "%=",
+            Token::AmpAssign() => "&=", Token::PipeAssign() => "|=", Token::CaretAssign() => "^=",
+            Token::ShlAssign() => "<<=", Token::ShrAssign() => ">>=",
+            Token::Ne() => "!=", Token::Lt() => "<", Token::Gt() => ">", Token::Le() => "<=", Token::Ge() => ">=",
+            Token::Not() => "!", Token::Tilde() => "~", Token::Amp() => "&", Token::Pipe() => "|",
+            Token::Caret() => "^", Token::Shl() => "<<", Token::Shr() => ">>",
+            Token::Arrow() => "->", Token::Dot() => ".", Token::Comma() => ",",
+            Token::Question() => "?", Token::Colon() => ":", Token::Ellipsis() => "...",
+            _ => return None
+        };
 
-        result
+        Some(text)
     }
+}
 
-    fn skip_number(&mut self, is_hex: bool) -> Option<String> {
-        if self.tok >= self.program.len() - 1 { return None }
-        
-        let mut result = String::new();
-        let mut c = self.curr_char();
-        while c == '0' || c == '1' || c == '2' || c == '3' ||
-              c == '4' || c == '5' || c == '6' || c ==  '7' || 
-              c == '8' || c == '9' || 
-              (is_hex && (
-                c == 'a' || c == 'b' || c == 'c' || c == 'd' || c == 'e' || c == 'f'
-              )) {
-            result.push(c);
-            self.tok += 1;
-            c = self.curr_char();
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Symbol(c) => write!(f, "{}", c),
+            Token::Id(name) => write!(f, "{}", name),
+            Token::Keyword(keyword) => write!(f, "{}", keyword),
+            Token::Num(value, _) => write!(f, "{}", value),
+            Token::Float(value) => write!(f, "{:?}", value),
+            Token::Str(text) => write!(f, "{:?}", text),
+            Token::Char(c) => write!(f, "{:?}", c),
+            _ => write!(f, "{}", self.spelling().unwrap_or_default())
         }
-        self.tok -= 1;
+    }
+}
+
+// Operator or punctuator spelled exactly `text`
+fn operator(text: &str) -> Option<Token> {
+    let token = match text {
+        "==" => Token::Eq(), "&&" => Token::And(), "||" => Token::Or(),
+        "+" => Token::Plus(), "-" => Token::Minus(), "*" => Token::Star(),
+        "/" => Token::Slash(), "%" => Token::Percent(),
+        "++" => Token::Inc(), "--" => Token::Dec(),
+        "=" => Token::Assign(),
+        "+=" => Token::PlusAssign(), "-=" => Token::MinusAssign(), "*=" => Token::StarAssign(),
+        "/=" => Token::SlashAssign(), "%=" => Token::PercentAssign(),
+        "&=" => Token::AmpAssign(), "|=" => Token::PipeAssign(), "^=" => Token::CaretAssign(),
+        "<<=" => Token::ShlAssign(), ">>=" => Token::ShrAssign(),
+        "!=" => Token::Ne(), "<" => Token::Lt(), ">" => Token::Gt(), "<=" => Token::Le(), ">=" => Token::Ge(),
+        "!" => Token::Not(), "~" => Token::Tilde(), "&" => Token::Amp(), "|" => Token::Pipe(),
+        "^" => Token::Caret(), "<<" => Token::Shl(), ">>" => Token::Shr(),
+        "->" => Token::Arrow(), "." => Token::Dot(), "," => Token::Comma(),
+        "?" => Token::Question(), ":" => Token::Colon(), "..." => Token::Ellipsis(),
+        _ => return None
+    };
+
+    Some(token)
+}
+
+// Location of a token in the source. `line` and `column` start at 1, the column counts
+// characters while `offset` and `len` are in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+    pub len: usize
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span
+}
+
+// Maps byte offsets in a source to lines and columns, and renders spans for diagnostics
+pub struct SourceMap {
+    pub source: String,
+    line_starts: Vec<usize>
+}
+
+impl SourceMap {
+    pub fn new(source: &str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(source.match_indices('\n').map(|(index, _)| index + 1));
+        Self { source: source.to_string(), line_starts }
+    }
+
+    // Span covering the bytes from `start` to `end`
+    pub fn span(&self, start: usize, end: usize) -> Span {
+        let line = self.line_starts.partition_point(|line_start| *line_start <= start);
+        let line_start = self.line_starts[line - 1];
+        let column = self.source[line_start..start].chars().count() + 1;
+        Span { offset: start, line, column, len: end - start }
+    }
 
-        Some(result)
+    pub fn line_text(&self, line: usize) -> &str {
+        let start = self.line_starts[line - 1];
+        let end = self.line_starts.get(line).map_or(self.source.len(), |next| next - 1);
+        self.source[start..end].trim_end_matches('\r')
     }
 
-    fn curr_char(&self) -> char {
-        self.program.chars().nth(self.tok).unwrap()
+    // The line containing `span` with the spanned text underlined:
+    //
+    //     3 |     8123 == zsd;
+    //       |             ^^^
+    pub fn snippet(&self, span: &Span) -> String {
+        self.snippet_at(span, span.line)
     }
 
-    fn clear_current_id(&mut self) {
-        if self.current_id.len() > 0 {
-            self.tokens.push(Token::Id(self.current_id.clone()));
-            self.current_id.clear();
+    // Same as `snippet`, with the line numbered `line` in the gutter, e.g. its line in the
+    // file it was included from
+    pub fn snippet_at(&self, span: &Span, line: usize) -> String {
+        let text = self.line_text(span.line);
+        let gutter = line.to_string();
+
+        let prefix: String = text.chars().take(span.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let end = (span.offset + span.len).min(self.line_starts[span.line - 1] + text.len());
+        let width = self.source.get(span.offset..end).map_or(0, |spanned| spanned.chars().count()).max(1);
+
+        format!("{} | {}\n{} | {}{}\n", gutter, text, " ".repeat(gutter.len()), prefix, "^".repeat(width))
+    }
+}
+
+impl Span {
+    // Span from the start of `self` to the end of `end`
+    pub fn to(self, end: Span) -> Span {
+        Span { len: (end.offset + end.len).saturating_sub(self.offset), ..self }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+// Lexical errors are reported as diagnostics
+pub type LexError = Diagnostic;
+
+// Position of the next character to be lexed
+#[derive(Debug, Clone, Copy)]
+struct Cursor {
+    offset: usize,
+    line: usize,
+    column: usize
+}
+
+// Lexes a borrowed source on demand, one token at a time. Lexing carries on after an error,
+// so every error in the source shows up as an `Err` item. Warnings are collected separately.
+pub struct TokenStream<'a> {
+    source: &'a str,
+    cursor: Cursor,
+    // Items lexed ahead of the caller, by `peek` or while looking for adjacent string literals
+    pending: VecDeque<Result<SpannedToken, LexError>>,
+    warnings: Vec<Diagnostic>
+}
+
+impl<'a> TokenStream<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self { source, cursor: Cursor { offset: 0, line: 1, column: 1 }, pending: VecDeque::new(), warnings: vec![] }
+    }
+
+    // Warnings for the source lexed so far, including any lexed ahead by `peek`
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    pub fn peek(&mut self) -> Option<&Result<SpannedToken, LexError>> {
+        self.peek_nth(0)
+    }
+
+    // The item `n` places after the next one, without consuming anything
+    pub fn peek_nth(&mut self, n: usize) -> Option<&Result<SpannedToken, LexError>> {
+        while self.pending.len() <= n && self.scan() {}
+        self.pending.get(n)
+    }
+
+    fn peek_char(&self, ahead: usize) -> Option<char> {
+        self.source[self.cursor.offset..].chars().nth(ahead)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek_char(0)?;
+        self.cursor.offset += c.len_utf8();
+        if c == '\n' {
+            self.cursor.line += 1;
+            self.cursor.column = 1;
+        } else {
+            self.cursor.column += 1;
         }
+
+        Some(c)
     }
 
-    pub fn set_program(&mut self, program: String) {
-        self.program = program;
-    } 
-    
-    pub fn lex(&mut self) {
-        let mut c = self.curr_char();
-        while self.tok < self.program.len() {
+    // Span from `start` to the current position
+    fn span_from(&self, start: Cursor) -> Span {
+        Span { offset: start.offset, line: start.line, column: start.column, len: self.cursor.offset - start.offset }
+    }
+
+    fn push(&mut self, token: Token, start: Cursor) {
+        let span = self.span_from(start);
+        self.pending.push_back(Ok(SpannedToken { token, span }));
+    }
+
+    fn error(&mut self, message: String, span: Span) {
+        self.pending.push_back(Err(Diagnostic::error(message, span)));
+    }
+
+    // Lexes until at least one item is queued; false at the end of the source
+    fn scan(&mut self) -> bool {
+        let queued = self.pending.len();
+        while self.pending.len() == queued {
+            self.skip_trivia();
+            if self.pending.len() != queued { break }
+
+            let start = self.cursor;
+            let c = match self.bump() {
+                Some(c) => c,
+                None => return false
+            };
             match c {
-                ' ' | '\n' | '\t' => self.clear_current_id(),
+                '(' | ')' | '[' | ']' | '{' | '}' | ';' => self.push(Token::Symbol(c), start),
+
+                '.' if self.peek_char(0).is_some_and(|next| next.is_ascii_digit()) => self.lex_number(start),
+
+                '=' |
+                '-' | '+' | '*' | '/'  | '%' |
+                '!' | '|' | '&' | '^' | '~' |
+                '<' | '>' | '.' | ',' | '?' | ':' => self.lex_operator(start),
 
-                '(' | ')' | '[' | ']' | '{' | '}' | ';' => {
-                    self.clear_current_id();
-                    self.tokens.push(Token::Symbol(c));
+                '0'..='9' => self.lex_number(start),
+
+                '"' => self.lex_string(start),
+
+                '\'' => {
+                    let text = self.read_literal(start, c);
+                    let mut chars = text.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(value), None) => self.push(Token::Char(value), start),
+                        (None, _) => self.error("empty character literal".to_string(), self.span_from(start)),
+                        (Some(value), Some(_)) => {
+                            self.error("multi-character character literal".to_string(), self.span_from(start));
+                            self.push(Token::Char(value), start);
+                        }
+                    }
                 },
 
-                '=' |  
-                '-' | '+' | '*' | '/'  | '%' | 
-                '!' | '|' | '&' | '^' | '~' => {
-                    self.clear_current_id();
-                    self.tok += 1;
-                    let next_char = self.curr_char();
-
-                    if c == '/' && next_char == '/' { self.skip_until('\n'); }
-                    if c == '=' && next_char == '=' { self.tokens.push(Token::Eq()); }
-                    if c == '|' && next_char == '|' { self.tokens.push(Token::Or()); }
-                    if c == '&' && next_char == '&' { self.tokens.push(Token::And()); }
+                'a'..='z' | 'A'..='Z' | '_' => {
+                    while self.peek_char(0).is_some_and(|c| c.is_ascii_alphanumeric() || c == '_') { self.bump(); }
+                    let text = &self.source[start.offset..self.cursor.offset];
+                    let token = match Keyword::from_str(text) {
+                        Ok(keyword) => Token::Keyword(keyword),
+                        Err(()) => Token::Id(text.to_string())
+                    };
+                    self.push(token, start);
                 },
 
-                '0' | '1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' | '9' => {
-                    if self.current_id.len() > 0 {
-                        self.current_id.push(c);
-                    } else {
-                        let num_str = self.skip_number(false).unwrap();
-                        self.tokens.push(Token::Num(num_str.parse::<usize>().unwrap()));
+                _ => {
+                    let message = if c.is_ascii_graphic() { format!("invalid character '{}'", c) } else { format!("invalid character {:?}", c) };
+                    self.error(message, self.span_from(start));
+                }
+            }
+        }
+
+        true
+    }
+
+    // Skips whitespace and comments. Comments don't nest, so an inner `/*` is only worth a warning.
+    fn skip_trivia(&mut self) {
+        loop {
+            match (self.peek_char(0), self.peek_char(1)) {
+                (Some(' ' | '\n' | '\t' | '\r' | '\x0b' | '\x0c'), _) => {
+                    self.bump();
+                },
+                (Some('/'), Some('/')) => {
+                    while self.peek_char(0).is_some_and(|c| c != '\n') { self.bump(); }
+                },
+                (Some('/'), Some('*')) => {
+                    let start = self.cursor;
+                    self.bump();
+                    self.bump();
+                    loop {
+                        match (self.peek_char(0), self.peek_char(1)) {
+                            (Some('*'), Some('/')) => {
+                                self.bump();
+                                self.bump();
+                                break;
+                            },
+                            (Some('/'), Some('*')) => {
+                                let span = Span { len: 2, ..self.span_from(self.cursor) };
+                                self.warnings.push(Diagnostic::warning("'/*' within block comment".to_string(), span));
+                                self.bump();
+                            },
+                            (Some(_), _) => {
+                                self.bump();
+                            },
+                            (None, _) => {
+                                self.error("unterminated comment".to_string(), Span { len: 2, ..self.span_from(start) });
+                                return;
+                            }
+                        }
                     }
                 },
+                _ => return
+            }
+        }
+    }
+
+    // Longest operator starting at `start` (maximal munch)
+    fn lex_operator(&mut self, start: Cursor) {
+        let rest = &self.source[start.offset..];
+        for len in (1..=3).rev() {
+            if let Some(token) = rest.get(..len).and_then(operator) {
+                for _ in 1..len { self.bump(); }
+                self.push(token, start);
+                return;
+            }
+        }
+    }
+
+    fn lex_number(&mut self, start: Cursor) {
+        // A preprocessing number: digits, letters, `_`, `.` and signed exponents
+        loop {
+            let text = &self.source[start.offset..self.cursor.offset];
+            let hex = text.starts_with("0x") || text.starts_with("0X");
+            let exponent_sign = match (self.peek_char(0), text.chars().last()) {
+                (Some('+' | '-'), Some('e' | 'E')) => !hex,
+                (Some('+' | '-'), Some('p' | 'P')) => hex,
+                _ => false
+            };
+            if !(exponent_sign || self.peek_char(0).is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')) { break }
+            self.bump();
+        }
+
+        let text = &self.source[start.offset..self.cursor.offset];
+        let span = self.span_from(start);
+        let lower = text.to_ascii_lowercase();
+        let (radix, digits) = if let Some(hex) = lower.strip_prefix("0x") {
+            (16, hex)
+        } else if let Some(bin) = lower.strip_prefix("0b") {
+            (2, bin)
+        } else {
+            (10, lower.as_str())
+        };
+
+        let is_float = if radix == 16 { digits.contains(['.', 'p']) } else { radix == 10 && digits.contains(['.', 'e']) };
+        if is_float {
+            let value = self.float_value(text, radix, span);
+            self.push(Token::Float(value), start);
+            return;
+        }
+
+        let split = digits.find(|c: char| !c.is_digit(radix)).unwrap_or(digits.len());
+        let (digits, suffix) = digits.split_at(split);
+        let (radix, digits) = if radix == 10 && digits.len() > 1 && digits.starts_with('0') {
+            (8, &digits[1..])
+        } else {
+            (radix, digits)
+        };
+
+        let suffix = match suffix {
+            "" => IntSuffix::default(),
+            "u" => IntSuffix { unsigned: true, long: false },
+            "l" | "ll" => IntSuffix { unsigned: false, long: true },
+            "ul" | "lu" | "ull" | "llu" => IntSuffix { unsigned: true, long: true },
+            _ => {
+                self.error(format!("invalid suffix on integer literal {}", text), span);
+                IntSuffix::default()
+            }
+        };
+
+        let value = if digits.is_empty() {
+            self.error(format!("integer literal {} has no digits", text), span);
+            0
+        } else if let Some(digit) = digits.chars().find(|c| !c.is_digit(radix)) {
+            self.error(format!("invalid digit '{}' in octal literal {}", digit, text), span);
+            0
+        } else {
+            match usize::from_str_radix(digits, radix) {
+                Ok(value) => value,
+                Err(_) => {
+                    self.error(format!("integer literal {} is too large", text), span);
+                    0
+                }
+            }
+        };
+
+        self.push(Token::Num(value, suffix), start);
+    }
+
+    fn float_value(&mut self, text: &str, radix: u32, span: Span) -> f64 {
+        let body = text.strip_suffix(['f', 'F', 'l', 'L']).unwrap_or(text);
+        if radix == 16 {
+            self.error(format!("hexadecimal floating literal {} is not supported", text), span);
+            return 0.0;
+        }
+
+        match body.parse::<f64>() {
+            Ok(value) if value.is_infinite() => {
+                self.error(format!("floating literal {} is out of range", text), span);
+                0.0
+            },
+            Ok(value) => value,
+            Err(_) => {
+                self.error(format!("invalid floating literal {}", text), span);
+                0.0
+            }
+        }
+    }
+
+    // Escape sequence after the backslash at `start`
+    fn read_escape(&mut self, start: Cursor) -> char {
+        let c = match self.peek_char(0) {
+            Some(c) if c != '\n' => c,
+            _ => return '\\'
+        };
+        self.bump();
+
+        match c {
+            'n' => '\n', 't' => '\t', 'r' => '\r', 'a' => '\x07', 'b' => '\x08',
+            'f' => '\x0c', 'v' => '\x0b', '\\' => '\\', '\'' => '\'', '"' => '"', '?' => '?',
+            'x' => {
+                let mut value = 0u32;
+                let mut digits = 0;
+                while let Some(digit) = self.peek_char(0).and_then(|c| c.to_digit(16)) {
+                    value = value.saturating_mul(16).saturating_add(digit);
+                    digits += 1;
+                    self.bump();
+                }
+                if digits == 0 {
+                    self.error("\\x used with no following hex digits".to_string(), self.span_from(start));
+                    return 'x';
+                }
+                match char::from_u32(value) {
+                    Some(c) if value <= 0xff => c,
+                    _ => {
+                        self.error("hex escape sequence out of range".to_string(), self.span_from(start));
+                        '\u{ff}'
+                    }
+                }
+            },
+            '0'..='7' => {
+                let mut value = c.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match self.peek_char(0).and_then(|c| c.to_digit(8)) {
+                        Some(digit) => {
+                            value = value * 8 + digit;
+                            self.bump();
+                        },
+                        None => break
+                    }
+                }
+                if value > 0xff {
+                    self.error("octal escape sequence out of range".to_string(), self.span_from(start));
+                    return '\u{ff}';
+                }
+                char::from_u32(value).unwrap()
+            },
+            _ => {
+                self.error(format!("unknown escape sequence '\\{}'", c), self.span_from(start));
+                c
+            }
+        }
+    }
 
-                '"' | '\'' => {
-                    self.clear_current_id();
+    // Body of a string or character literal whose opening `quote` is at `start`. Stops before
+    // the end of the line if the literal isn't closed.
+    fn read_literal(&mut self, start: Cursor, quote: char) -> String {
+        let mut result = String::new();
+        loop {
+            let escape = self.cursor;
+            match self.peek_char(0) {
+                Some(c) if c == quote => {
+                    self.bump();
+                    return result;
                 },
+                Some('\n') | None => {
+                    let kind = if quote == '"' { "string" } else { "character" };
+                    self.error(format!("unterminated {} literal", kind), Span { len: 1, ..self.span_from(start) });
+                    return result;
+                },
+                Some('\\') => {
+                    self.bump();
+                    let c = self.read_escape(escape);
+                    result.push(c);
+                },
+                Some(c) => {
+                    self.bump();
+                    result.push(c);
+                }
+            }
+        }
+    }
 
-                _ => self.current_id.push(c) 
+    // Adjacent string literals are concatenated into one token
+    fn lex_string(&mut self, start: Cursor) {
+        let mut text = self.read_literal(start, '"');
+        loop {
+            let (end, queued) = (self.cursor, self.pending.len());
+            self.skip_trivia();
+            if self.peek_char(0) != Some('"') {
+                self.cursor = end;
+                self.pending.truncate(queued);
+                break;
             }
-            self.tok += 1;
 
-            if self.tok >= self.program.len() { break }
-            c = self.curr_char();
+            let next = self.cursor;
+            self.bump();
+            text += &self.read_literal(next, '"');
+        }
+
+        self.push(Token::Str(text), start);
+    }
+}
+
+impl Iterator for TokenStream<'_> {
+    type Item = Result<SpannedToken, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pending.is_empty() { self.scan(); }
+        self.pending.pop_front()
+    }
+}
+
+// Lexes a whole program at once, collecting what a `TokenStream` produces
+pub struct Lexer {
+    pub program: String,
+    pub source_map: SourceMap
+}
+
+impl Lexer {
+    pub fn new() -> Self {
+        Self { program: String::new(), source_map: SourceMap::new("") }
+    }
+
+    pub fn set_program(&mut self, program: String) {
+        self.source_map = SourceMap::new(&program);
+        self.program = program;
+    }
+
+    // Lexes the whole program, reporting every lexical error instead of stopping at the first.
+    // Warnings are merged in with the errors in source order.
+    pub fn lex(&mut self) -> (Vec<SpannedToken>, Vec<Diagnostic>) {
+        let mut tokens = vec![];
+        let mut diagnostics = vec![];
+        let mut stream = TokenStream::new(&self.program);
+        for item in stream.by_ref() {
+            match item {
+                Ok(token) => tokens.push(token),
+                Err(diagnostic) => diagnostics.push(diagnostic)
+            }
         }
+        diagnostics.extend_from_slice(stream.warnings());
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span().map(|span| span.offset));
+
+        (tokens, diagnostics)
     }
-}
\ No newline at end of file
+}
+
+impl Default for Lexer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
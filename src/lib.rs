@@ -0,0 +1,19 @@
+pub mod assembler;
+pub mod ast;
+pub mod builder;
+pub mod conditional;
+pub mod diagnostic;
+pub mod lexer;
+pub mod preprocessor;
+pub mod parser;
+pub mod printer;
+pub mod sema;
+pub mod typeck;
+pub mod ir;
+pub mod peephole;
+pub mod cfg;
+pub mod isa;
+pub mod disassembler;
+pub mod header;
+pub mod output;
+pub mod verifier;
@@ -1,45 +1,206 @@
-use std::{fs::File, io::Read, str::FromStr, env};
-use crate::{assembler::Parser, lexer::Lexer};
+use std::{fs::File, io::Read};
+use compiler::{assembler::{self, Parser as _}, cfg, diagnostic::Severity, disassembler, header, isa, lexer::{Lexer, SourceMap}, output, parser::Parser, peephole, preprocessor::Preprocessor, printer, sema, typeck, verifier};
 
-pub mod assembler;
-pub mod lexer;
-pub mod parser;
-pub mod ir;
+struct Options {
+    path: Option<String>,
+    optimize: bool,
+    dot: Option<String>,
+    js_enums: Option<String>,
+    disassemble: bool,
+    verify: bool,
+    format: Option<output::OutputFormat>,
+    symbols: bool,
+    data: bool,
+    defines: Vec<(String, String)>,
+    include_paths: Vec<String>,
+    dump_ast: bool,
+    print_source: bool
+}
+
+fn parse_args() -> Options {
+    let mut options = Options { path: None, optimize: false, dot: None, js_enums: None, disassemble: false, verify: false,
+        format: None, symbols: false, data: false, defines: vec![], include_paths: vec![], dump_ast: false, print_source: false };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-O" => options.optimize = true,
+            "--dot" => options.dot = args.next(),
+            "--emit-js-enums" => options.js_enums = args.next(),
+            "--disassemble" => options.disassemble = true,
+            "--verify" => options.verify = true,
+            "--format" => {
+                let name = args.next().unwrap_or_default();
+                match output::OutputFormat::from_name(&name) {
+                    Some(format) => options.format = Some(format),
+                    None => panic!("Unknown output format: {} (expected fragment, module, json or u32)", name)
+                }
+            },
+            "--symbols" => options.symbols = true,
+            "--data" => options.data = true,
+            "--dump-ast" => options.dump_ast = true,
+            "--print-source" => options.print_source = true,
+            "-D" => {
+                let define = args.next().unwrap_or_default();
+                let (name, value) = define.split_once('=').unwrap_or((&define, "1"));
+                options.defines.push((name.to_string(), value.to_string()));
+            },
+            "-I" => options.include_paths.push(args.next().unwrap_or_default()),
+            _ if arg.starts_with("-I") => options.include_paths.push(arg[2..].to_string()),
+            _ => options.path = Some(arg)
+        }
+    }
+
+    options
+}
 
 fn main() {
-    // test_assembly()
+    let options = parse_args();
+    if let Some(js_path) = &options.js_enums {
+        if let Err(err) = std::fs::write(js_path, isa::js_enums()) { panic!("Failed to write {}: {}", js_path, err) }
+        if options.path.is_none() { return }
+    }
+    if options.path.is_none() { panic!("Usage: ./compiler [-D NAME[=VALUE]] [-I <dir>] [-O] [--dot <file>] [--emit-js-enums <file>] [--disassemble] [--verify]\n\t[--format fragment|module|json|u32] [--symbols] [--data] [--dump-ast] [--print-source] <input file>") }
 
-    let mut args = std::env::args();
-    let path_option = args.nth(1);
-    if let None = path_option { panic!("Usage: ./compiler <input file>") }
+    let path = options.path.as_ref().unwrap();
+    if path.ends_with(".asm") {
+        return test_assembly(path, &options);
+    }
 
-    let path = path_option.unwrap();
     let mut buf = String::new();
-    let file = File::open(&path);
-    if let Err(_) = file { panic!("Failed to open file {}", &path) }
+    let file = File::open(path);
+    if file.is_err() { panic!("Failed to open file {}", &path) }
     let _ = File::read_to_string(&mut file.unwrap(), &mut buf);
 
+    if options.verify {
+        let errors = verifier::verify_output(&buf);
+        for err in &errors {
+            eprintln!("{}", err);
+        }
+        if !errors.is_empty() { std::process::exit(1) }
+        return;
+    }
+
+    if options.disassemble {
+        let words = match output::read_words(&buf) {
+            Ok(words) => words,
+            Err(err) => panic!("ERROR: {}", err)
+        };
+        let code = match header::code_of(&words) {
+            Ok(code) => code,
+            Err(err) => panic!("ERROR: header: {}", err)
+        };
+        match disassembler::disassemble(code) {
+            Ok(listing) => print!("{}", listing),
+            Err(err) => panic!("ERROR: {}", err)
+        }
+        return;
+    }
+
+    let mut preprocessor = Preprocessor::new();
+    for (name, value) in &options.defines {
+        preprocessor.define(name, value);
+    }
+    for dir in &options.include_paths {
+        preprocessor.add_include_path(dir);
+    }
+    let preprocessed = match preprocessor.preprocess(&buf, path) {
+        Ok(preprocessed) => preprocessed,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
     let mut lexer = Lexer::new();
-    lexer.set_program(buf);
-    lexer.lex();
-    println!("{:?}", lexer.tokens);
-}
+    lexer.set_program(preprocessed.text.clone());
+    let (tokens, diagnostics) = lexer.lex();
+    if !options.dump_ast && !options.print_source {
+        for token in &tokens {
+            let (file, line) = preprocessed.location(token.span.line);
+            println!("{}:{}:{}\t{:?}", file, line, token.span.column, token.token);
+        }
+    }
+    let mut parser = Parser::new();
+    parser.set_tokens(tokens);
+    let (mut unit, parse_diagnostics) = parser.parse();
+    let mut diagnostics = [diagnostics, parse_diagnostics].concat();
+    // Names are only resolved in a well-formed tree, so recovery can't cause spurious errors
+    if !diagnostics.iter().any(|diagnostic| diagnostic.severity == Severity::Error) {
+        let (symbols, sema_diagnostics) = sema::analyze(&mut unit);
+        diagnostics.extend(sema_diagnostics);
+        diagnostics.extend(typeck::check(&mut unit, &symbols));
+    }
+
+    for diagnostic in &diagnostics {
+        match diagnostic.span() {
+            Some(span) => {
+                let (file, line) = preprocessed.location(span.line);
+                eprint!("{}:{}:{}: {}: {}\n{}", file, line, span.column, diagnostic.severity, diagnostic.message,
+                    lexer.source_map.snippet_at(span, line));
+            },
+            None => eprintln!("{}: {}", path, diagnostic)
+        }
+    }
+    if diagnostics.iter().any(|diagnostic| diagnostic.severity == Severity::Error) { std::process::exit(1) }
 
-fn test_assembly() {
-    let mut args = std::env::args();
-    let path_option = args.nth(1);
-    if let None = path_option { panic!("Usage: ./assembler <input file>") }
+    if options.dump_ast { print!("{}", printer::dump(&unit)) }
+    if options.print_source { print!("{}", printer::print(&unit)) }
+}
 
-    let path = path_option.unwrap();
+fn test_assembly(path: &str, options: &Options) {
     let mut buf = String::new();
-    let file = File::open(&path);
-    if let Err(_) = file { panic!("Failed to open file {}", &path) }
+    let file = File::open(path);
+    if file.is_err() { panic!("Failed to open file {}", &path) }
 
     let mut prog = assembler::Program::new();
+    for (name, value) in &options.defines {
+        prog.define(name, value);
+    }
     let _ = File::read_to_string(&mut file.unwrap(), &mut buf);
 
-    prog.parse(buf);
+    prog.parse(buf.clone());
+    let source_map = SourceMap::new(&buf);
+    if !prog.diagnostics.is_empty() {
+        for diagnostic in &prog.diagnostics {
+            eprint!("{}:{}", path, diagnostic.render(&source_map));
+        }
+        std::process::exit(1);
+    }
+    if options.optimize {
+        let saved = peephole::optimize(&mut prog);
+        eprintln!("peephole: saved {} words", saved);
+    }
+
+    let cfg = cfg::Cfg::build(&prog);
+    let diagnostics = cfg.check();
+    for diagnostic in &diagnostics {
+        eprint!("{}:{}", path, diagnostic.render(&source_map));
+    }
+    if let Some(dot_path) = &options.dot {
+        if let Err(err) = std::fs::write(dot_path, cfg.to_dot()) { panic!("Failed to write {}: {}", dot_path, err) }
+    }
+    if diagnostics.iter().any(|diagnostic| diagnostic.severity == Severity::Error) {
+        std::process::exit(1);
+    }
+
+    if let Some(format) = options.format {
+        let emit_options = output::EmitOptions { format, symbols: options.symbols, data: options.data };
+        match output::emit(&prog, &emit_options) {
+            Ok(text) => print!("{}", text),
+            Err(err) => {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     println!("{}", prog);
-    let bytecode = prog.assemble();
-    println!("{}", bytecode);
+    match prog.assemble() {
+        Ok(bytecode) => println!("{}", bytecode),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+    }
 }
@@ -0,0 +1,192 @@
+use crate::assembler::Program;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    // Bare `Op.*` list, as returned by `Program::assemble`
+    Fragment,
+    // ES module exporting the header, program array and entry point
+    Module,
+    // Header followed by the code, or an object when symbols or data are included
+    Json,
+    // `new Uint32Array([...])` expression holding the header, code and data section
+    TypedArray
+}
+
+pub struct EmitOptions {
+    pub format: OutputFormat,
+    pub symbols: bool,
+    pub data: bool
+}
+
+impl OutputFormat {
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        match name {
+            "fragment" => Some(OutputFormat::Fragment),
+            "module" | "js" => Some(OutputFormat::Module),
+            "json" => Some(OutputFormat::Json),
+            "u32" | "typed-array" => Some(OutputFormat::TypedArray),
+            _ => None
+        }
+    }
+}
+
+fn join(words: &[u32]) -> String {
+    words.iter().map(|word| word.to_string()).collect::<Vec<String>>().join(", ")
+}
+
+// One instruction per line, each line indented and terminated with a comma
+fn code_lines(program: &Program, indent: &str) -> Result<String, String> {
+    let mut result = String::new();
+    for words in program.encode_instructions()? {
+        result += &format!("{}{},\n", indent, join(&words));
+    }
+
+    Ok(result)
+}
+
+fn symbol_entries(program: &Program, indent: &str, separator: &str) -> String {
+    program.symbols().iter()
+        .map(|(name, offset)| format!("{}\"{}\"{}{}", indent, name, separator, offset))
+        .collect::<Vec<String>>()
+        .join(",\n")
+}
+
+fn emit_module(program: &Program, options: &EmitOptions) -> Result<String, String> {
+    let header = program.header(options.data)?;
+    let mut result = format!("export const header = [{}];\n\n", join(&header.to_words()));
+    result += "export const program = [\n";
+    result += &code_lines(program, "    ")?;
+    result += &format!("];\n\nexport const entry = {};\n", header.entry);
+
+    if options.symbols {
+        result += &format!("\nexport const symbols = {{\n{}\n}};\n", symbol_entries(program, "    ", ": "));
+    }
+    if options.data {
+        result += &format!("\nexport const data = [{}];\n", join(&program.data));
+    }
+
+    Ok(result)
+}
+
+fn emit_json(program: &Program, options: &EmitOptions) -> Result<String, String> {
+    let header = program.header(options.data)?;
+    let code = program.encode()?;
+    if !options.symbols && !options.data {
+        return Ok(format!("[{}]\n", join(&[header.to_words(), code].concat())));
+    }
+
+    let mut fields = vec![
+        format!("  \"header\": [{}]", join(&header.to_words())),
+        format!("  \"program\": [{}]", join(&code)),
+        format!("  \"entry\": {}", header.entry)
+    ];
+    if options.symbols {
+        fields.push(format!("  \"symbols\": {{\n{}\n  }}", symbol_entries(program, "    ", ": ")));
+    }
+    if options.data {
+        fields.push(format!("  \"data\": [{}]", join(&program.data)));
+    }
+
+    Ok(format!("{{\n{}\n}}\n", fields.join(",\n")))
+}
+
+fn emit_typed_array(program: &Program, options: &EmitOptions) -> Result<String, String> {
+    let mut result = String::new();
+    if options.symbols {
+        result += "/* symbols:\n";
+        for (name, offset) in program.symbols() {
+            result += &format!(" * {} = {}\n", name, offset);
+        }
+        result += " */\n";
+    }
+
+    result += "new Uint32Array([\n";
+    result += &format!("    /* header */ {},\n", join(&program.header(options.data)?.to_words()));
+    result += &code_lines(program, "    ")?;
+    if options.data && !program.data.is_empty() {
+        result += &format!("    /* data */ {},\n", join(&program.data));
+    }
+    result += "])\n";
+
+    Ok(result)
+}
+
+// Fails if the code can't be encoded, e.g. when a branch target or the `.entry` label is
+// not defined
+pub fn emit(program: &Program, options: &EmitOptions) -> Result<String, String> {
+    match options.format {
+        OutputFormat::Fragment => program.assemble(),
+        OutputFormat::Module => emit_module(program, options),
+        OutputFormat::Json => emit_json(program, options),
+        OutputFormat::TypedArray => emit_typed_array(program, options)
+    }
+}
+
+// Strips `/* ... */` comments, which the typed array output uses for the header and symbols
+fn strip_comments(text: &str) -> Result<String, String> {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("/*") {
+        result += &rest[..start];
+        match rest[start..].find("*/") {
+            Some(end) => rest = &rest[start + end + 2..],
+            None => return Err("unterminated comment".to_string())
+        }
+    }
+    result += rest;
+
+    Ok(result)
+}
+
+// Comma or whitespace separated words, e.g. the contents of an array literal
+fn parse_list(list: &str) -> Result<Vec<u32>, String> {
+    list.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|item| !item.is_empty())
+        .map(|item| item.parse::<u32>().map_err(|_| format!("invalid word '{}'", item)))
+        .collect()
+}
+
+// Contents of the array literal assigned to `name`, written `"name": [...]` in JSON and
+// `const name = [...]` in a module
+fn field<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    for pattern in [format!("\"{}\"", name), format!("const {}", name)] {
+        for (start, _) in text.match_indices(&pattern) {
+            let rest = text[start + pattern.len()..].trim_start();
+            let Some(rest) = rest.strip_prefix(':').or_else(|| rest.strip_prefix('=')) else { continue };
+            let Some(rest) = rest.trim_start().strip_prefix('[') else { continue };
+            return rest.find(']').map(|end| &rest[..end]);
+        }
+    }
+
+    None
+}
+
+/// Reads back the words of an image written in any of the numeric output formats: the header,
+/// code and data section of a module, JSON document or typed array, or a bare list of words.
+pub fn read_words(text: &str) -> Result<Vec<u32>, String> {
+    let text = strip_comments(text)?;
+    let trimmed = text.trim();
+
+    if trimmed.starts_with('{') || trimmed.contains("export const") {
+        let mut words = vec![];
+        for name in ["header", "program", "data"] {
+            match field(trimmed, name) {
+                Some(list) => words.extend(parse_list(list)?),
+                None if name == "data" => {},
+                None => return Err(format!("missing \"{}\" array", name))
+            }
+        }
+        return Ok(words);
+    }
+
+    let list = match trimmed.strip_prefix("new Uint32Array(") {
+        Some(rest) => rest.strip_suffix(')').ok_or("expected ')' after the typed array")?.trim(),
+        None => trimmed
+    };
+    let list = match list.strip_prefix('[') {
+        Some(rest) => rest.strip_suffix(']').ok_or("expected ']' at the end of the array")?,
+        None => list
+    };
+
+    parse_list(list)
+}
@@ -1,36 +1,741 @@
-use crate::{ir::Function, lexer::Token};
+use crate::{ast::*, diagnostic::Diagnostic, lexer::{Keyword, Span, SpannedToken, Token}};
+
+type ParseResult<T> = Result<T, Diagnostic>;
+
+// Type, storage class and qualifiers at the start of a declaration
+struct Specifiers {
+    ty: Type,
+    storage: Option<Storage>,
+    is_const: bool,
+    // Set when the specifiers define a struct: `struct point { int x; int y; }`
+    struct_decl: Option<StructDecl>,
+    span: Span
+}
+
+fn is_specifier(keyword: Keyword) -> bool {
+    matches!(keyword,
+        Keyword::Void | Keyword::Char | Keyword::Short | Keyword::Int | Keyword::Long | Keyword::Float |
+        Keyword::Double | Keyword::Signed | Keyword::Unsigned | Keyword::Struct | Keyword::Const |
+        Keyword::Volatile | Keyword::Restrict | Keyword::Static | Keyword::Extern | Keyword::Register |
+        Keyword::Auto | Keyword::Inline)
+}
 
 pub struct Parser {
-    pub functions: Vec<Function>,
-    pub tokens: Vec<Token>,
-    pub tok: usize
+    pub tokens: Vec<SpannedToken>,
+    pub tok: usize,
+    // Syntax errors recovered from so far
+    diagnostics: Vec<Diagnostic>
 }
 
 impl Parser {
     pub fn new() -> Self {
-        Self { functions: vec![], tokens: vec![], tok: 0 }
+        Self { tokens: vec![], tok: 0, diagnostics: vec![] }
     }
 
-    pub fn set_tokens(&mut self, tokens: Vec<Token>) {
+    pub fn set_tokens(&mut self, tokens: Vec<SpannedToken>) {
         self.tokens = tokens;
+        self.tok = 0;
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.tok + n).map(|token| &token.token)
+    }
+
+    // Span of the current token, or an empty span just past the last one at the end of input
+    fn span(&self) -> Span {
+        match self.tokens.get(self.tok) {
+            Some(token) => token.span,
+            None => self.tokens.last().map_or(Span::default(), |last| Span { offset: last.span.offset + last.span.len, len: 0, column: last.span.column + last.span.len, ..last.span })
+        }
+    }
+
+    // Span of the last token consumed
+    fn previous_span(&self) -> Span {
+        self.tokens[self.tok.saturating_sub(1)].span
+    }
+
+    fn advance(&mut self) -> Option<&SpannedToken> {
+        let token = self.tokens.get(self.tok);
+        if token.is_some() { self.tok += 1 }
+        token
+    }
+
+    fn is(&self, token: &Token) -> bool {
+        self.peek() == Some(token)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let found = self.is(token);
+        if found { self.tok += 1 }
+        found
+    }
+
+    fn expected(&self, what: &str) -> Diagnostic {
+        let found = match self.peek() {
+            Some(token) => format!("'{}'", token),
+            None => "end of input".to_string()
+        };
+        Diagnostic::error(format!("expected {}, found {}", what, found), self.span())
+    }
+
+    fn expect(&mut self, token: &Token) -> ParseResult<Span> {
+        if !self.is(token) { return Err(self.expected(&format!("'{}'", token))) }
+        self.tok += 1;
+        Ok(self.previous_span())
+    }
+
+    // A `;` ending a statement or declaration. When it's missing at the end of a line, or before
+    // a `}`, the error is recorded and parsing carries on as if it were there.
+    fn expect_semicolon(&mut self) -> ParseResult<Span> {
+        if self.eat(&Token::Symbol(';')) { return Ok(self.previous_span()) }
+
+        let diagnostic = self.expected("';'");
+        let previous = self.previous_span();
+        if self.is(&Token::Symbol('}')) || self.span().line > previous.line {
+            self.diagnostics.push(diagnostic);
+            return Ok(previous);
+        }
+
+        Err(diagnostic)
+    }
+
+    fn expect_id(&mut self) -> ParseResult<(String, Span)> {
+        match self.peek() {
+            Some(Token::Id(name)) => {
+                let name = name.clone();
+                self.tok += 1;
+                Ok((name, self.previous_span()))
+            },
+            _ => Err(self.expected("an identifier"))
+        }
+    }
+
+    fn starts_declaration(&self) -> bool {
+        matches!(self.peek(), Some(Token::Keyword(keyword)) if is_specifier(*keyword))
+    }
+
+    // Parses the whole translation unit. Syntax errors are recorded and parsing resumes at the
+    // next statement or declaration, so every error in the file is reported.
+    pub fn parse(&mut self) -> (TranslationUnit, Vec<Diagnostic>) {
+        let mut items = vec![];
+        while self.peek().is_some() {
+            let start = self.tok;
+            if let Err(diagnostic) = self.external_decl(&mut items) {
+                self.diagnostics.push(diagnostic);
+                self.synchronize(start, false);
+            }
+        }
+
+        (TranslationUnit { items }, std::mem::take(&mut self.diagnostics))
+    }
+
+    // Skips the rest of a broken statement or declaration that started at token `start`: up
+    // to and including the next `;`, or a `}` closing a block opened along the way. Inside a
+    // block, stops before the `}` closing it and before keywords that start a statement.
+    fn synchronize(&mut self, start: usize, in_block: bool) {
+        let mut depth = 0;
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Symbol(';') if depth == 0 => {
+                    self.tok += 1;
+                    return;
+                },
+                Token::Symbol('{') => depth += 1,
+                Token::Symbol('}') if depth == 0 => {
+                    if !in_block { self.tok += 1 }
+                    return;
+                },
+                Token::Symbol('}') => {
+                    depth -= 1;
+                    if depth == 0 && !in_block {
+                        self.tok += 1;
+                        return;
+                    }
+                },
+                Token::Keyword(Keyword::If | Keyword::While | Keyword::For | Keyword::Do | Keyword::Return |
+                    Keyword::Break | Keyword::Continue) if in_block && depth == 0 && self.tok > start => return,
+                _ => {}
+            }
+            self.tok += 1;
+        }
+    }
+
+    // A function definition, or a declaration of structs, prototypes and global variables
+    fn external_decl(&mut self, items: &mut Vec<ExternalDecl>) -> ParseResult<()> {
+        if !self.starts_declaration() { return Err(self.expected("a declaration")) }
+        let specifiers = self.specifiers()?;
+        if let Some(decl) = specifiers.struct_decl.clone() { items.push(ExternalDecl::Struct(decl)) }
+        if self.eat(&Token::Symbol(';')) { return Ok(()) }
+
+        let mut first = true;
+        loop {
+            let start = self.span();
+            let ty = self.pointers(specifiers.ty.clone());
+            let (name, _) = self.expect_id()?;
+
+            if self.is(&Token::Symbol('(')) {
+                let (params, variadic, prototyped) = self.params()?;
+                let decl = FunctionDecl { name, return_type: ty, params, variadic, prototyped, storage: specifiers.storage, symbol: None, span: specifiers.span.to(self.previous_span()) };
+                if first && self.is(&Token::Symbol('{')) {
+                    let body = self.block()?;
+                    items.push(ExternalDecl::Function(FunctionDef { decl, body }));
+                    return Ok(());
+                }
+                items.push(ExternalDecl::Prototype(decl));
+            } else {
+                let ty = self.arrays(ty)?;
+                items.push(ExternalDecl::Variable(self.var_decl(name, ty, &specifiers, start)?));
+            }
+
+            first = false;
+            if !self.eat(&Token::Comma()) { break }
+        }
+        self.expect_semicolon()?;
+
+        Ok(())
+    }
+
+    fn specifiers(&mut self) -> ParseResult<Specifiers> {
+        let start = self.span();
+        let mut storage = None;
+        let mut is_const = false;
+        let mut unsigned = None;
+        let mut short = false;
+        let mut longs = 0;
+        let mut base: Option<Type> = None;
+        let mut struct_decl = None;
+
+        while let Some(Token::Keyword(keyword)) = self.peek() {
+            let keyword = *keyword;
+            if !is_specifier(keyword) { break }
+            let span = self.span();
+            self.tok += 1;
+
+            match keyword {
+                Keyword::Static | Keyword::Extern => {
+                    if storage.is_some() { return Err(Diagnostic::error("multiple storage classes in declaration".to_string(), span)) }
+                    storage = Some(if keyword == Keyword::Static { Storage::Static } else { Storage::Extern });
+                },
+                Keyword::Const => is_const = true,
+                Keyword::Volatile | Keyword::Restrict | Keyword::Register | Keyword::Auto | Keyword::Inline => {},
+                Keyword::Signed | Keyword::Unsigned => {
+                    if unsigned.is_some() { return Err(Diagnostic::error(format!("duplicate '{}'", keyword), span)) }
+                    unsigned = Some(keyword == Keyword::Unsigned);
+                },
+                Keyword::Short => short = true,
+                Keyword::Long => longs += 1,
+                _ => {
+                    if base.is_some() { return Err(Diagnostic::error("two or more data types in declaration".to_string(), span)) }
+                    base = Some(match keyword {
+                        Keyword::Void => Type::Void,
+                        Keyword::Char => Type::Int { kind: IntKind::Char, unsigned: false },
+                        Keyword::Int => Type::Int { kind: IntKind::Int, unsigned: false },
+                        Keyword::Float => Type::Float,
+                        Keyword::Double => Type::Double,
+                        _ => {
+                            let (name, _) = self.expect_id()?;
+                            if self.is(&Token::Symbol('{')) {
+                                struct_decl = Some(self.struct_fields(name.clone(), span)?);
+                            }
+                            Type::Struct(name)
+                        }
+                    });
+                }
+            }
+        }
+
+        let span = start.to(self.previous_span());
+        let sized = short || longs > 0;
+        let ty = match base {
+            None if !sized && unsigned.is_none() => return Err(Diagnostic::error("expected a type".to_string(), start)),
+            Some(Type::Int { kind: IntKind::Char, .. }) if !sized => Type::Int { kind: IntKind::Char, unsigned: unsigned == Some(true) },
+            None | Some(Type::Int { kind: IntKind::Int, .. }) if !(short && longs > 0) && longs <= 2 => {
+                let kind = if short { IntKind::Short } else if longs > 0 { IntKind::Long } else { IntKind::Int };
+                Type::Int { kind, unsigned: unsigned == Some(true) }
+            },
+            Some(Type::Double) if !short && longs <= 1 && unsigned.is_none() => Type::Double,
+            Some(ty) if !sized && unsigned.is_none() => ty,
+            _ => return Err(Diagnostic::error("invalid combination of type specifiers".to_string(), span))
+        };
+
+        Ok(Specifiers { ty, storage, is_const, struct_decl, span })
+    }
+
+    fn struct_fields(&mut self, name: String, start: Span) -> ParseResult<StructDecl> {
+        self.expect(&Token::Symbol('{'))?;
+        let mut fields = vec![];
+        while !self.eat(&Token::Symbol('}')) {
+            if !self.starts_declaration() { return Err(self.expected("a field declaration or '}'")) }
+            let specifiers = self.specifiers()?;
+            if specifiers.struct_decl.is_some() || specifiers.storage.is_some() {
+                return Err(Diagnostic::error("nested struct definitions and storage classes are not supported in fields".to_string(), specifiers.span));
+            }
+            loop {
+                let (name, ty, span) = self.declarator(specifiers.ty.clone())?;
+                fields.push(Field { ty, name, span: specifiers.span.to(span) });
+                if !self.eat(&Token::Comma()) { break }
+            }
+            self.expect_semicolon()?;
+        }
+
+        Ok(StructDecl { name, fields, span: start.to(self.previous_span()) })
+    }
+
+    fn pointers(&mut self, mut ty: Type) -> Type {
+        while self.eat(&Token::Star()) {
+            ty = Type::Pointer(Box::new(ty));
+            while matches!(self.peek(), Some(Token::Keyword(Keyword::Const | Keyword::Volatile | Keyword::Restrict))) { self.tok += 1 }
+        }
+
+        ty
+    }
+
+    // `[N]` suffixes, the first one being the outermost array
+    fn arrays(&mut self, ty: Type) -> ParseResult<Type> {
+        let mut lengths = vec![];
+        while self.eat(&Token::Symbol('[')) {
+            let length = match self.peek() {
+                Some(Token::Num(length, _)) => {
+                    let length = *length;
+                    self.tok += 1;
+                    Some(length)
+                },
+                _ => None
+            };
+            self.expect(&Token::Symbol(']'))?;
+            lengths.push(length);
+        }
+
+        Ok(lengths.into_iter().rev().fold(ty, |ty, length| Type::Array(Box::new(ty), length)))
+    }
+
+    // Pointers, name and array lengths of a declared variable, field or named parameter
+    fn declarator(&mut self, ty: Type) -> ParseResult<(String, Type, Span)> {
+        let start = self.span();
+        let ty = self.pointers(ty);
+        let (name, _) = self.expect_id()?;
+        let ty = self.arrays(ty)?;
+
+        Ok((name, ty, start.to(self.previous_span())))
+    }
+
+    // Parameters, whether they end with `...`, and whether they are given at all: `()` is the
+    // only list that isn't a prototype
+    fn params(&mut self) -> ParseResult<(Vec<Param>, bool, bool)> {
+        self.expect(&Token::Symbol('('))?;
+        let mut params = vec![];
+        if self.eat(&Token::Symbol(')')) { return Ok((params, false, false)) }
+        if self.is(&Token::Keyword(Keyword::Void)) && self.peek_nth(1) == Some(&Token::Symbol(')')) {
+            self.tok += 2;
+            return Ok((params, false, true));
+        }
+
+        loop {
+            if self.eat(&Token::Ellipsis()) {
+                self.expect(&Token::Symbol(')'))?;
+                return Ok((params, true, true));
+            }
+            if !self.starts_declaration() { return Err(self.expected("a parameter type")) }
+
+            let specifiers = self.specifiers()?;
+            if specifiers.storage.is_some() || specifiers.struct_decl.is_some() {
+                return Err(Diagnostic::error("invalid parameter declaration".to_string(), specifiers.span));
+            }
+            let ty = self.pointers(specifiers.ty);
+            let name = match self.peek() {
+                Some(Token::Id(_)) => Some(self.expect_id()?.0),
+                _ => None
+            };
+            let ty = self.arrays(ty)?;
+            params.push(Param { ty, name, symbol: None, span: specifiers.span.to(self.previous_span()) });
+
+            if !self.eat(&Token::Comma()) { break }
+        }
+        self.expect(&Token::Symbol(')'))?;
+
+        Ok((params, false, true))
+    }
+
+    // The rest of a declarator after its name and type: the initializer, if any
+    fn var_decl(&mut self, name: String, ty: Type, specifiers: &Specifiers, start: Span) -> ParseResult<VarDecl> {
+        let init = if self.eat(&Token::Assign()) { Some(self.initializer()?) } else { None };
+        let span = start.to(self.previous_span());
+
+        Ok(VarDecl { name, ty, storage: specifiers.storage, is_const: specifiers.is_const, init, symbol: None, span })
+    }
+
+    fn initializer(&mut self) -> ParseResult<Initializer> {
+        if !self.is(&Token::Symbol('{')) { return Ok(Initializer::Expr(self.assignment()?)) }
+
+        let start = self.expect(&Token::Symbol('{'))?;
+        let mut items = vec![];
+        while !self.is(&Token::Symbol('}')) {
+            items.push(self.initializer()?);
+            if !self.eat(&Token::Comma()) { break }
+        }
+        let end = self.expect(&Token::Symbol('}'))?;
+
+        Ok(Initializer::List(items, start.to(end)))
     }
 
-    pub fn advance(&mut self) -> &Token {
+    fn block(&mut self) -> ParseResult<Block> {
+        let start = self.expect(&Token::Symbol('{'))?;
+        let mut stmts = vec![];
+        while !self.is(&Token::Symbol('}')) {
+            if self.peek().is_none() { return Err(self.expected("'}'")) }
+            let start = self.tok;
+            if let Err(diagnostic) = self.block_item(&mut stmts) {
+                self.diagnostics.push(diagnostic);
+                self.synchronize(start, true);
+            }
+        }
+        let end = self.expect(&Token::Symbol('}'))?;
+
+        Ok(Block { stmts, span: start.to(end) })
+    }
+
+    // A statement, or a declaration of block-scoped variables
+    fn block_item(&mut self, stmts: &mut Vec<Stmt>) -> ParseResult<()> {
+        if !self.starts_declaration() {
+            stmts.push(self.statement()?);
+            return Ok(());
+        }
+
+        for decl in self.local_decls()? {
+            stmts.push(Stmt { span: decl.span, kind: StmtKind::Decl(decl) });
+        }
+
+        Ok(())
+    }
+
+    // Declaration of block-scoped variables, up to and including its `;`
+    fn local_decls(&mut self) -> ParseResult<Vec<VarDecl>> {
+        let specifiers = self.specifiers()?;
+        if specifiers.struct_decl.is_some() {
+            return Err(Diagnostic::error("struct definitions are only supported at file scope".to_string(), specifiers.span));
+        }
+
+        let mut decls = vec![];
+        loop {
+            let start = self.span();
+            let (name, ty, _) = self.declarator(specifiers.ty.clone())?;
+            decls.push(self.var_decl(name, ty, &specifiers, start)?);
+            if !self.eat(&Token::Comma()) { break }
+        }
+        self.expect_semicolon()?;
+
+        Ok(decls)
+    }
+
+    fn statement(&mut self) -> ParseResult<Stmt> {
+        let start = self.span();
+        let kind = match self.peek() {
+            Some(Token::Symbol('{')) => {
+                let block = self.block()?;
+                return Ok(Stmt { span: block.span, kind: StmtKind::Block(block) });
+            },
+            Some(Token::Symbol(';')) => {
+                self.tok += 1;
+                StmtKind::Empty
+            },
+            // An `else` belongs to the closest `if`, so the dangling else needs nothing special
+            Some(Token::Keyword(Keyword::If)) => {
+                self.tok += 1;
+                let condition = self.condition()?;
+                let then = self.statement()?;
+                let otherwise = if self.eat(&Token::Keyword(Keyword::Else)) { Some(Box::new(self.statement()?)) } else { None };
+                StmtKind::If(condition, Box::new(then), otherwise)
+            },
+            Some(Token::Keyword(Keyword::While)) => {
+                self.tok += 1;
+                let condition = self.condition()?;
+                StmtKind::While(condition, Box::new(self.statement()?))
+            },
+            Some(Token::Keyword(Keyword::Do)) => {
+                self.tok += 1;
+                let body = self.statement()?;
+                self.expect(&Token::Keyword(Keyword::While))?;
+                let condition = self.condition()?;
+                self.expect_semicolon()?;
+                StmtKind::DoWhile(Box::new(body), condition)
+            },
+            Some(Token::Keyword(Keyword::For)) => {
+                self.tok += 1;
+                self.expect(&Token::Symbol('('))?;
+                let init = if self.eat(&Token::Symbol(';')) {
+                    None
+                } else if self.starts_declaration() {
+                    Some(ForInit::Decl(self.local_decls()?))
+                } else {
+                    let init = self.expression()?;
+                    self.expect(&Token::Symbol(';'))?;
+                    Some(ForInit::Expr(init))
+                };
+                let condition = if self.is(&Token::Symbol(';')) { None } else { Some(self.expression()?) };
+                self.expect(&Token::Symbol(';'))?;
+                let step = if self.is(&Token::Symbol(')')) { None } else { Some(self.expression()?) };
+                self.expect(&Token::Symbol(')'))?;
+                StmtKind::For { init, condition, step, body: Box::new(self.statement()?) }
+            },
+            Some(Token::Keyword(Keyword::Break)) => {
+                self.tok += 1;
+                self.expect_semicolon()?;
+                StmtKind::Break
+            },
+            Some(Token::Keyword(Keyword::Continue)) => {
+                self.tok += 1;
+                self.expect_semicolon()?;
+                StmtKind::Continue
+            },
+            Some(Token::Keyword(Keyword::Return)) => {
+                self.tok += 1;
+                let value = if self.is(&Token::Symbol(';')) { None } else { Some(self.expression()?) };
+                self.expect_semicolon()?;
+                StmtKind::Return(value)
+            },
+            _ => {
+                let expr = self.expression()?;
+                self.expect_semicolon()?;
+                StmtKind::Expr(expr)
+            }
+        };
+
+        Ok(Stmt { kind, span: start.to(self.previous_span()) })
+    }
+
+    // Parenthesized condition of an `if`, `while` or `do`
+    fn condition(&mut self) -> ParseResult<Expr> {
+        self.expect(&Token::Symbol('('))?;
+        let condition = self.expression()?;
+        self.expect(&Token::Symbol(')'))?;
+
+        Ok(condition)
+    }
+
+    // A full expression, including the comma operator
+    pub fn expression(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.assignment()?;
+        while self.eat(&Token::Comma()) {
+            let right = self.assignment()?;
+            expr = binary(BinaryOp::Comma, expr, right);
+        }
+
+        Ok(expr)
+    }
+
+    // Assignments are right-associative: `a = b += c` is `a = (b += c)`
+    fn assignment(&mut self) -> ParseResult<Expr> {
+        let target = self.conditional()?;
+        let op = match self.peek() {
+            Some(Token::Assign()) => None,
+            Some(Token::PlusAssign()) => Some(BinaryOp::Add),
+            Some(Token::MinusAssign()) => Some(BinaryOp::Sub),
+            Some(Token::StarAssign()) => Some(BinaryOp::Mul),
+            Some(Token::SlashAssign()) => Some(BinaryOp::Div),
+            Some(Token::PercentAssign()) => Some(BinaryOp::Mod),
+            Some(Token::AmpAssign()) => Some(BinaryOp::BitAnd),
+            Some(Token::PipeAssign()) => Some(BinaryOp::BitOr),
+            Some(Token::CaretAssign()) => Some(BinaryOp::BitXor),
+            Some(Token::ShlAssign()) => Some(BinaryOp::Shl),
+            Some(Token::ShrAssign()) => Some(BinaryOp::Shr),
+            _ => return Ok(target)
+        };
         self.tok += 1;
-        &self.tokens[self.tok]
+
+        let value = self.assignment()?;
+        let span = target.span.to(value.span);
+        Ok(Expr { kind: ExprKind::Assign(op, Box::new(target), Box::new(value)), ty: None, span })
+    }
+
+    fn conditional(&mut self) -> ParseResult<Expr> {
+        let condition = self.binary(1)?;
+        if !self.eat(&Token::Question()) { return Ok(condition) }
+
+        let then = self.expression()?;
+        self.expect(&Token::Colon())?;
+        let otherwise = self.conditional()?;
+        let span = condition.span.to(otherwise.span);
+        Ok(Expr { kind: ExprKind::Conditional(Box::new(condition), Box::new(then), Box::new(otherwise)), ty: None, span })
+    }
+
+    // Left-associative binary operators binding at least as tightly as `min_precedence`
+    fn binary(&mut self, min_precedence: u8) -> ParseResult<Expr> {
+        let mut left = self.unary()?;
+        while let Some(op) = self.peek().and_then(binary_op) {
+            let precedence = op.precedence();
+            if precedence < min_precedence { break }
+            self.tok += 1;
+            let right = self.binary(precedence + 1)?;
+            left = binary(op, left, right);
+        }
+
+        Ok(left)
     }
 
-    pub fn advance_and_expect_id(&mut self, expect: Token) -> Option<Token> {
-        let token = self.advance();
+    fn unary(&mut self) -> ParseResult<Expr> {
+        let start = self.span();
+        let op = match self.peek() {
+            Some(Token::Minus()) => UnaryOp::Neg,
+            Some(Token::Plus()) => UnaryOp::Plus,
+            Some(Token::Not()) => UnaryOp::Not,
+            Some(Token::Tilde()) => UnaryOp::BitNot,
+            Some(Token::Star()) => UnaryOp::Deref,
+            Some(Token::Amp()) => UnaryOp::AddrOf,
+            Some(Token::Inc()) => UnaryOp::PreInc,
+            Some(Token::Dec()) => UnaryOp::PreDec,
+            Some(Token::Keyword(Keyword::Sizeof)) => {
+                self.tok += 1;
+                if self.is(&Token::Symbol('(')) && self.starts_type_name(1) {
+                    self.tok += 1;
+                    let ty = self.type_name()?;
+                    let end = self.expect(&Token::Symbol(')'))?;
+                    return Ok(Expr { kind: ExprKind::SizeofType(ty), ty: None, span: start.to(end) });
+                }
+                let operand = self.unary()?;
+                let span = start.to(operand.span);
+                return Ok(Expr { kind: ExprKind::SizeofExpr(Box::new(operand)), ty: None, span });
+            },
+            Some(Token::Symbol('(')) if self.starts_type_name(1) => {
+                self.tok += 1;
+                let ty = self.type_name()?;
+                self.expect(&Token::Symbol(')'))?;
+                let operand = self.unary()?;
+                let span = start.to(operand.span);
+                return Ok(Expr { kind: ExprKind::Cast(ty, Box::new(operand)), ty: None, span });
+            },
+            _ => {
+                let primary = self.primary()?;
+                return self.postfix(primary);
+            }
+        };
+        self.tok += 1;
 
+        let operand = self.unary()?;
+        let span = start.to(operand.span);
+        Ok(Expr { kind: ExprKind::Unary(op, Box::new(operand)), ty: None, span })
     }
 
-    pub fn parse(&mut self) {
-        let mut tok = &self.tokens[self.tok];
-        while self.tok < self.tokens.len() {
-            let function_type = tok;
-            let function_name = self.advance();
+    // Calls, indexing, member access and postfix `++`/`--` applied to `expr`
+    fn postfix(&mut self, mut expr: Expr) -> ParseResult<Expr> {
+        loop {
+            let start = expr.span;
+            let kind = match self.peek() {
+                Some(Token::Symbol('(')) => {
+                    self.tok += 1;
+                    let mut args = vec![];
+                    if !self.is(&Token::Symbol(')')) {
+                        loop {
+                            args.push(self.assignment()?);
+                            if !self.eat(&Token::Comma()) { break }
+                        }
+                    }
+                    self.expect(&Token::Symbol(')'))?;
+                    ExprKind::Call(Box::new(expr), args)
+                },
+                Some(Token::Symbol('[')) => {
+                    self.tok += 1;
+                    let index = self.expression()?;
+                    self.expect(&Token::Symbol(']'))?;
+                    ExprKind::Index(Box::new(expr), Box::new(index))
+                },
+                Some(Token::Dot()) => {
+                    self.tok += 1;
+                    ExprKind::Member(Box::new(expr), self.expect_id()?.0)
+                },
+                Some(Token::Arrow()) => {
+                    self.tok += 1;
+                    ExprKind::Arrow(Box::new(expr), self.expect_id()?.0)
+                },
+                Some(Token::Inc()) => {
+                    self.tok += 1;
+                    ExprKind::Unary(UnaryOp::PostInc, Box::new(expr))
+                },
+                Some(Token::Dec()) => {
+                    self.tok += 1;
+                    ExprKind::Unary(UnaryOp::PostDec, Box::new(expr))
+                },
+                _ => return Ok(expr)
+            };
+            let span = start.to(self.previous_span());
+            expr = Expr { kind, ty: None, span };
+        }
+    }
+
+    // Whether the token `n` places ahead starts a type, as in a cast or `sizeof(type)`
+    fn starts_type_name(&self, n: usize) -> bool {
+        matches!(self.peek_nth(n), Some(Token::Keyword(keyword)) if is_specifier(*keyword))
+    }
 
+    // A type without a name: `unsigned char *`, `int [4]`
+    fn type_name(&mut self) -> ParseResult<Type> {
+        let specifiers = self.specifiers()?;
+        if specifiers.storage.is_some() || specifiers.struct_decl.is_some() {
+            return Err(Diagnostic::error("expected a type name".to_string(), specifiers.span));
         }
+        let ty = self.pointers(specifiers.ty);
+        self.arrays(ty)
     }
-}
\ No newline at end of file
+
+    fn primary(&mut self) -> ParseResult<Expr> {
+        let start = self.span();
+        let kind = match self.peek() {
+            Some(Token::Id(name)) => ExprKind::Ident(name.clone(), None),
+            Some(Token::Num(value, suffix)) => ExprKind::Int(*value, *suffix),
+            Some(Token::Float(value)) => ExprKind::Float(*value),
+            Some(Token::Str(text)) => ExprKind::Str(text.clone()),
+            Some(Token::Char(c)) => ExprKind::Char(*c),
+            Some(Token::Symbol('(')) => {
+                self.tok += 1;
+                let expr = self.expression()?;
+                let end = self.expect(&Token::Symbol(')'))?;
+                return Ok(Expr { kind: expr.kind, ty: None, span: start.to(end) });
+            },
+            _ => return Err(self.expected("an expression"))
+        };
+        self.advance();
+
+        Ok(Expr { kind, ty: None, span: start })
+    }
+}
+
+fn binary(op: BinaryOp, left: Expr, right: Expr) -> Expr {
+    let span = left.span.to(right.span);
+    Expr { kind: ExprKind::Binary(op, Box::new(left), Box::new(right)), ty: None, span }
+}
+
+// Binary operator of a token; `BinaryOp::precedence` gives its binding strength
+fn binary_op(token: &Token) -> Option<BinaryOp> {
+    let op = match token {
+        Token::Or() => BinaryOp::Or,
+        Token::And() => BinaryOp::And,
+        Token::Pipe() => BinaryOp::BitOr,
+        Token::Caret() => BinaryOp::BitXor,
+        Token::Amp() => BinaryOp::BitAnd,
+        Token::Eq() => BinaryOp::Eq,
+        Token::Ne() => BinaryOp::Ne,
+        Token::Lt() => BinaryOp::Lt,
+        Token::Gt() => BinaryOp::Gt,
+        Token::Le() => BinaryOp::Le,
+        Token::Ge() => BinaryOp::Ge,
+        Token::Shl() => BinaryOp::Shl,
+        Token::Shr() => BinaryOp::Shr,
+        Token::Plus() => BinaryOp::Add,
+        Token::Minus() => BinaryOp::Sub,
+        Token::Star() => BinaryOp::Mul,
+        Token::Slash() => BinaryOp::Div,
+        Token::Percent() => BinaryOp::Mod,
+        _ => return None
+    };
+
+    Some(op)
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,100 @@
+use crate::{assembler::{Arg, Instruction, Label, Program, REG_SP}, isa::PUSH_SIZE};
+
+// `add reg, reg, value`
+fn add_imm(reg: u16, value: u32) -> Instruction {
+    Instruction { mnemonic: "add", cond: None, args: vec![Arg::Reg(reg), Arg::Reg(reg), Arg::Imm(value)] }
+}
+
+fn is_noop(insn: &Instruction) -> bool {
+    match (insn.mnemonic, insn.args.as_slice()) {
+        ("mov", [Arg::Reg(dest), Arg::Reg(src)]) => dest == src,
+        ("add", [Arg::Reg(dest), Arg::Reg(src), Arg::Imm(0)]) => dest == src,
+        _ => false
+    }
+}
+
+// Rewrites a pair of adjacent instructions. Returns None if the pair can't be simplified,
+// otherwise the (possibly empty) replacement.
+fn merge_pair(first: &Instruction, second: &Instruction) -> Option<Vec<Instruction>> {
+    match ((first.mnemonic, first.args.as_slice()), (second.mnemonic, second.args.as_slice())) {
+        (
+            ("add", [Arg::Reg(dest1), Arg::Reg(src1), Arg::Imm(value1)]),
+            ("add", [Arg::Reg(dest2), Arg::Reg(src2), Arg::Imm(value2)])
+        ) if dest1 == src1 && dest2 == src2 && dest1 == dest2 => {
+            let value = value1.checked_add(*value2)?;
+            Some(vec![add_imm(*dest1, value)])
+        },
+        (
+            ("push", _),
+            ("add", [Arg::Reg(REG_SP), Arg::Reg(REG_SP), Arg::Imm(value)])
+        ) if *value >= PUSH_SIZE => {
+            if *value == PUSH_SIZE {
+                Some(vec![])
+            } else {
+                Some(vec![add_imm(REG_SP, value - PUSH_SIZE)])
+            }
+        },
+        _ => None
+    }
+}
+
+fn simplify_label(label: &mut Label) -> bool {
+    let mut changed = false;
+
+    let before = label.instructions.len();
+    label.instructions.retain(|insn| !is_noop(insn));
+    changed |= label.instructions.len() != before;
+
+    let mut i = 0;
+    while i + 1 < label.instructions.len() {
+        if let Some(replacement) = merge_pair(&label.instructions[i], &label.instructions[i + 1]) {
+            label.instructions.splice(i..i + 2, replacement);
+            changed = true;
+            i = i.saturating_sub(1);
+        } else {
+            i += 1;
+        }
+    }
+
+    changed
+}
+
+// A branch at the end of a label is redundant when it targets the very next instruction,
+// i.e. any of the following labels up to (and including) the first non-empty one.
+fn branches_to_next(labels: &[Label], index: usize) -> bool {
+    let target = match labels[index].instructions.last().map(|insn| (insn.mnemonic, insn.args.as_slice())) {
+        Some(("b", [Arg::Label(target)])) => target,
+        _ => return false
+    };
+
+    for label in &labels[index + 1..] {
+        if label.name == *target { return true }
+        if !label.instructions.is_empty() { break }
+    }
+
+    false
+}
+
+/// Removes redundant instructions from `program` without crossing label boundaries, so every
+/// label still points at the same logical instruction. Returns the number of words saved.
+pub fn optimize(program: &mut Program) -> usize {
+    let before = program.code_size();
+
+    loop {
+        let mut changed = false;
+        for label in program.labels.iter_mut() {
+            changed |= simplify_label(label);
+        }
+
+        for index in 0..program.labels.len() {
+            if branches_to_next(&program.labels, index) {
+                program.labels[index].instructions.pop();
+                changed = true;
+            }
+        }
+
+        if !changed { break }
+    }
+
+    before - program.code_size()
+}
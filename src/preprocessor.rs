@@ -0,0 +1,820 @@
+use std::{collections::{HashMap, HashSet}, fmt, fs, path::{Path, PathBuf}};
+
+// C preprocessor, run over the source before lexing. Handles `#include`, object-like and
+// function-like `#define` (with `#` and `##`), `#undef`, `#if`/`#ifdef`/`#ifndef`/`#elif`/
+// `#else`/`#endif` and `#error`. Directive lines and lines in skipped groups become empty
+// lines, so `Preprocessed::location` can map every output line back to its file and line.
+
+const MAX_INCLUDE_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Macro {
+    // `None` for object-like macros
+    params: Option<Vec<String>>,
+    body: String
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreprocessError {
+    pub file: String,
+    pub line: usize,
+    pub message: String
+}
+
+#[derive(Debug)]
+pub struct Preprocessed {
+    pub text: String,
+    pub files: Vec<String>,
+    // (index in `files`, line) of every output line
+    lines: Vec<(usize, usize)>
+}
+
+struct Group {
+    // Whether the enclosing group is being kept
+    parent_active: bool,
+    // Whether one of the branches so far was taken
+    taken: bool,
+    active: bool,
+    seen_else: bool,
+    line: usize
+}
+
+pub struct Preprocessor {
+    macros: HashMap<String, Macro>,
+    include_paths: Vec<PathBuf>,
+    files: Vec<String>,
+    lines: Vec<(usize, usize)>,
+    text: String,
+    depth: usize,
+    // Location of the line being processed
+    file: String,
+    line: usize
+}
+
+impl Preprocessed {
+    // File and line that output line `line` came from
+    pub fn location(&self, line: usize) -> (&str, usize) {
+        match self.lines.get(line.wrapping_sub(1)) {
+            Some((file, line)) => (&self.files[*file], *line),
+            None => (self.files.first().map_or("", |file| file.as_str()), line)
+        }
+    }
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Self {
+            macros: HashMap::new(),
+            include_paths: vec![],
+            files: vec![],
+            lines: vec![],
+            text: String::new(),
+            depth: 0,
+            file: String::new(),
+            line: 0
+        }
+    }
+
+    pub fn add_include_path(&mut self, path: &str) {
+        self.include_paths.push(PathBuf::from(path));
+    }
+
+    // Object-like macro, as defined by `-D NAME=VALUE`
+    pub fn define(&mut self, name: &str, value: &str) {
+        self.macros.insert(name.to_string(), Macro { params: None, body: value.to_string() });
+    }
+
+    pub fn preprocess_file(&mut self, path: &str) -> Result<Preprocessed, PreprocessError> {
+        match fs::read_to_string(path) {
+            Ok(source) => self.preprocess(&source, path),
+            Err(err) => Err(PreprocessError { file: path.to_string(), line: 0, message: format!("cannot read file: {}", err) })
+        }
+    }
+
+    pub fn preprocess(&mut self, source: &str, file: &str) -> Result<Preprocessed, PreprocessError> {
+        self.text.clear();
+        self.files.clear();
+        self.lines.clear();
+        self.process(source, file)?;
+
+        Ok(Preprocessed { text: std::mem::take(&mut self.text), files: std::mem::take(&mut self.files), lines: std::mem::take(&mut self.lines) })
+    }
+
+    fn error(&self, message: String) -> PreprocessError {
+        PreprocessError { file: self.file.clone(), line: self.line, message }
+    }
+
+    fn emit(&mut self, text: &str, file: usize, line: usize) {
+        if !self.lines.is_empty() { self.text.push('\n') }
+        self.text += text;
+        self.lines.push((file, line));
+    }
+
+    fn process(&mut self, source: &str, file: &str) -> Result<(), PreprocessError> {
+        let index = self.files.len();
+        self.files.push(file.to_string());
+        let outer = (std::mem::replace(&mut self.file, file.to_string()), self.line);
+
+        let mut groups: Vec<Group> = vec![];
+        let mut in_comment = false;
+        let physical: Vec<&str> = source.split('\n').map(|line| line.strip_suffix('\r').unwrap_or(line)).collect();
+
+        let mut number = 0;
+        while number < physical.len() {
+            // Lines ending in a backslash are joined with the next one
+            let first = number;
+            let mut text = String::new();
+            while let Some(part) = physical[number].strip_suffix('\\').filter(|_| number + 1 < physical.len()) {
+                text += part;
+                number += 1;
+            }
+            text += physical[number];
+            number += 1;
+            self.line = first + 1;
+
+            let active = groups.last().is_none_or(|group| group.active);
+            let starts_in_comment = in_comment;
+            in_comment = comment_state(&text, in_comment);
+
+            let trimmed = text.trim_start();
+            if !starts_in_comment && trimmed.starts_with('#') {
+                let directive = strip_comments(&trimmed[1..]);
+                let directive = directive.trim();
+                let (name, rest) = match directive.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')) {
+                    Some(end) => (&directive[..end], directive[end..].trim()),
+                    None => (directive, "")
+                };
+                let included = self.directive(name, rest, active, &mut groups)?;
+                if !included { self.emit("", index, first + 1) }
+            } else if active {
+                let expanded = self.expand(&text, &HashSet::new(), starts_in_comment)?;
+                self.emit(&expanded, index, first + 1);
+            } else {
+                self.emit("", index, first + 1);
+            }
+
+            for line in first + 1..number {
+                self.emit("", index, line + 1);
+            }
+        }
+
+        if let Some(group) = groups.last() {
+            self.line = group.line;
+            return Err(self.error("#if without #endif".to_string()));
+        }
+
+        (self.file, self.line) = outer;
+        Ok(())
+    }
+
+    // Handles one directive; returns whether it included a file in its place
+    fn directive(&mut self, name: &str, rest: &str, active: bool, groups: &mut Vec<Group>) -> Result<bool, PreprocessError> {
+        match name {
+            "if" | "ifdef" | "ifndef" => {
+                let condition = active && match name {
+                    "ifdef" => self.macros.contains_key(&self.single_name(rest, name)?),
+                    "ifndef" => !self.macros.contains_key(&self.single_name(rest, name)?),
+                    _ => self.condition(rest)?
+                };
+                groups.push(Group { parent_active: active, taken: condition, active: condition, seen_else: false, line: self.line });
+            },
+            "elif" => {
+                let group = match groups.last() {
+                    Some(group) if !group.seen_else => group,
+                    Some(_) => return Err(self.error("#elif after #else".to_string())),
+                    None => return Err(self.error("#elif without #if".to_string()))
+                };
+                let condition = group.parent_active && !group.taken && self.condition(rest)?;
+                let group = groups.last_mut().unwrap();
+                group.active = condition;
+                group.taken |= condition;
+            },
+            "else" => {
+                let group = match groups.last_mut() {
+                    Some(group) if !group.seen_else => group,
+                    Some(_) => return Err(self.error("duplicate #else".to_string())),
+                    None => return Err(self.error("#else without #if".to_string()))
+                };
+                group.active = group.parent_active && !group.taken;
+                group.taken = true;
+                group.seen_else = true;
+            },
+            "endif" => {
+                if groups.pop().is_none() { return Err(self.error("#endif without #if".to_string())) }
+            },
+            _ if !active => {},
+            "define" => self.define_directive(rest)?,
+            "undef" => {
+                let name = self.single_name(rest, "undef")?;
+                self.macros.remove(&name);
+            },
+            "include" => {
+                self.include(rest)?;
+                return Ok(true);
+            },
+            "error" => return Err(self.error(format!("#error {}", rest))),
+            "pragma" | "" => {},
+            _ => return Err(self.error(format!("unknown directive #{}", name)))
+        }
+
+        Ok(false)
+    }
+
+    fn single_name(&self, rest: &str, directive: &str) -> Result<String, PreprocessError> {
+        let mut words = rest.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some(name), None) if is_identifier(name) => Ok(name.to_string()),
+            _ => Err(self.error(format!("#{} expects a single name", directive)))
+        }
+    }
+
+    fn define_directive(&mut self, rest: &str) -> Result<(), PreprocessError> {
+        let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+        let name = &rest[..end];
+        if !is_identifier(name) { return Err(self.error("#define expects a macro name".to_string())) }
+
+        // Function-like only when the parameter list follows the name without a space
+        let after = &rest[end..];
+        let mac = match after.strip_prefix('(') {
+            Some(params) => {
+                let close = match params.find(')') {
+                    Some(close) => close,
+                    None => return Err(self.error(format!("missing ')' in parameter list of macro {}", name)))
+                };
+                let list: Vec<String> = params[..close].split(',').map(|param| param.trim().to_string()).collect();
+                let list = if list.len() == 1 && list[0].is_empty() { vec![] } else { list };
+                if let Some(param) = list.iter().find(|param| !is_identifier(param)) {
+                    return Err(self.error(format!("invalid parameter '{}' of macro {}", param, name)));
+                }
+                Macro { params: Some(list), body: params[close + 1..].trim().to_string() }
+            },
+            None => Macro { params: None, body: after.trim().to_string() }
+        };
+
+        self.macros.insert(name.to_string(), mac);
+        Ok(())
+    }
+
+    fn include(&mut self, rest: &str) -> Result<(), PreprocessError> {
+        let (name, quoted) = if let Some(name) = rest.strip_prefix('"').and_then(|rest| rest.split_once('"')) {
+            (name.0, true)
+        } else if let Some(name) = rest.strip_prefix('<').and_then(|rest| rest.split_once('>')) {
+            (name.0, false)
+        } else {
+            return Err(self.error("#include expects \"file\" or <file>".to_string()));
+        };
+
+        let mut candidates = vec![];
+        if quoted {
+            candidates.push(Path::new(&self.file).parent().unwrap_or(Path::new("")).join(name));
+        }
+        candidates.extend(self.include_paths.iter().map(|dir| dir.join(name)));
+
+        let (path, source) = match candidates.iter().find_map(|path| fs::read_to_string(path).ok().map(|source| (path, source))) {
+            Some(found) => found,
+            None => return Err(self.error(format!("cannot find include file {}", name)))
+        };
+        if self.depth == MAX_INCLUDE_DEPTH {
+            return Err(self.error(format!("#include nested more than {} levels deep", MAX_INCLUDE_DEPTH)));
+        }
+
+        self.depth += 1;
+        let result = self.process(&source, &path.to_string_lossy());
+        self.depth -= 1;
+        result
+    }
+
+    // Replaces macros in `text`, leaving comments, string and character literals and numbers
+    // alone. Macros in `hide` are being expanded and aren't replaced again.
+    fn expand(&self, text: &str, hide: &HashSet<String>, in_comment: bool) -> Result<String, PreprocessError> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut result = String::new();
+        let mut in_comment = in_comment;
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            let next = chars.get(i + 1).copied();
+            if in_comment {
+                if c == '*' && next == Some('/') {
+                    in_comment = false;
+                    result += "*/";
+                    i += 2;
+                } else {
+                    result.push(c);
+                    i += 1;
+                }
+            } else if c == '/' && next == Some('/') {
+                result.extend(&chars[i..]);
+                break;
+            } else if c == '/' && next == Some('*') {
+                in_comment = true;
+                result += "/*";
+                i += 2;
+            } else if c == '"' || c == '\'' {
+                let end = literal_end(&chars, i);
+                result.extend(&chars[i..end]);
+                i = end;
+            } else if c.is_ascii_digit() || c == '.' && next.is_some_and(|next| next.is_ascii_digit()) {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.') { i += 1 }
+                result.extend(&chars[start..i]);
+            } else if c.is_ascii_alphabetic() || c == '_' {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') { i += 1 }
+                let name: String = chars[start..i].iter().collect();
+                i = self.expand_name(&name, &chars, i, hide, &mut result)?;
+            } else {
+                result.push(c);
+                i += 1;
+            }
+        }
+
+        Ok(result)
+    }
+
+    // Appends the expansion of the identifier `name`, which ends before `chars[i]`, and returns
+    // where scanning continues
+    fn expand_name(&self, name: &str, chars: &[char], i: usize, hide: &HashSet<String>, result: &mut String) -> Result<usize, PreprocessError> {
+        match name {
+            "__LINE__" => {
+                *result += &self.line.to_string();
+                return Ok(i);
+            },
+            "__FILE__" => {
+                *result += &format!("\"{}\"", self.file.replace('\\', "\\\\").replace('"', "\\\""));
+                return Ok(i);
+            },
+            _ => {}
+        }
+
+        let mac = match self.macros.get(name) {
+            Some(mac) if !hide.contains(name) => mac,
+            _ => {
+                *result += name;
+                return Ok(i);
+            }
+        };
+        let mut hide = hide.clone();
+        hide.insert(name.to_string());
+
+        let params = match &mac.params {
+            Some(params) => params,
+            None => {
+                *result += &self.expand(&substitute(&mac.body, &[], &[], &[]), &hide, false)?;
+                return Ok(i);
+            }
+        };
+
+        // A function-like macro name not followed by `(` is left alone
+        let mut open = i;
+        while open < chars.len() && chars[open].is_whitespace() { open += 1 }
+        if chars.get(open) != Some(&'(') {
+            *result += name;
+            return Ok(i);
+        }
+
+        let (args, end) = match macro_arguments(chars, open) {
+            Some(found) => found,
+            None => return Err(self.error(format!("unterminated argument list for macro {}", name)))
+        };
+        let args = if params.is_empty() && args.len() == 1 && args[0].trim().is_empty() { vec![] } else { args };
+        if args.len() != params.len() {
+            return Err(self.error(format!("macro {} expects {} arguments, found {}", name, params.len(), args.len())));
+        }
+
+        let expanded = args.iter().map(|arg| self.expand(arg, &HashSet::new(), false)).collect::<Result<Vec<String>, _>>()?;
+        *result += &self.expand(&substitute(&mac.body, params, &args, &expanded), &hide, false)?;
+        Ok(end)
+    }
+
+    // Value of an `#if` or `#elif` expression
+    fn condition(&self, expr: &str) -> Result<bool, PreprocessError> {
+        let expr = self.replace_defined(expr)?;
+        let expr = self.expand(&expr, &HashSet::new(), false)?;
+        let tokens = match expression_tokens(&expr) {
+            Ok(tokens) => tokens,
+            Err(message) => return Err(self.error(message))
+        };
+
+        let mut evaluator = Evaluator { tokens, pos: 0, skipping: 0 };
+        let value = evaluator.conditional().and_then(|value| match evaluator.tokens.get(evaluator.pos) {
+            Some(token) => Err(format!("unexpected '{}' in #if expression", token)),
+            None => Ok(value)
+        });
+        match value {
+            Ok(value) => Ok(value != 0),
+            Err(message) => Err(self.error(message))
+        }
+    }
+
+    // Replaces `defined NAME` and `defined(NAME)` with 1 or 0
+    fn replace_defined(&self, expr: &str) -> Result<String, PreprocessError> {
+        let mut result = String::new();
+        let mut rest = expr;
+        while let Some(start) = find_identifier(rest, "defined") {
+            result += &rest[..start];
+            let after = rest[start + "defined".len()..].trim_start();
+            let (name, after) = match after.strip_prefix('(') {
+                Some(inner) => match inner.split_once(')') {
+                    Some((name, after)) => (name.trim(), after),
+                    None => return Err(self.error("missing ')' after defined".to_string()))
+                },
+                None => {
+                    let end = after.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(after.len());
+                    (&after[..end], &after[end..])
+                }
+            };
+            if !is_identifier(name) { return Err(self.error("defined expects a macro name".to_string())) }
+
+            result += if self.macros.contains_key(name) { " 1 " } else { " 0 " };
+            rest = after;
+        }
+
+        Ok(result + rest)
+    }
+}
+
+impl Default for Preprocessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Byte offset of `name` as a whole identifier in `text`
+fn find_identifier(text: &str, name: &str) -> Option<usize> {
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    text.match_indices(name).map(|(start, _)| start).find(|start| {
+        !text[..*start].ends_with(is_word) && !text[start + name.len()..].starts_with(is_word)
+    })
+}
+
+// Index just past the string or character literal starting at `chars[start]`
+fn literal_end(chars: &[char], start: usize) -> usize {
+    let quote = chars[start];
+    let mut i = start + 1;
+    while i < chars.len() && chars[i] != quote {
+        if chars[i] == '\\' { i += 1 }
+        i += 1;
+    }
+
+    (i + 1).min(chars.len())
+}
+
+// Whether a block comment is still open at the end of `line`
+fn comment_state(line: &str, in_comment: bool) -> bool {
+    let chars: Vec<char> = line.chars().collect();
+    let mut in_comment = in_comment;
+    let mut i = 0;
+    while i < chars.len() {
+        let next = chars.get(i + 1).copied();
+        if in_comment {
+            if chars[i] == '*' && next == Some('/') {
+                in_comment = false;
+                i += 1;
+            }
+        } else if chars[i] == '/' && next == Some('/') {
+            break;
+        } else if chars[i] == '/' && next == Some('*') {
+            in_comment = true;
+            i += 1;
+        } else if chars[i] == '"' || chars[i] == '\'' {
+            i = literal_end(&chars, i) - 1;
+        }
+        i += 1;
+    }
+
+    in_comment
+}
+
+// Directive text with comments replaced by a space
+fn strip_comments(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut result = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let next = chars.get(i + 1).copied();
+        if chars[i] == '/' && next == Some('/') {
+            break;
+        } else if chars[i] == '/' && next == Some('*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) { i += 1 }
+            result.push(' ');
+            i += 2;
+        } else if chars[i] == '"' || chars[i] == '\'' {
+            let end = literal_end(&chars, i);
+            result.extend(&chars[i..end]);
+            i = end;
+        } else {
+            result.push(chars[i]);
+            i += 1;
+        }
+    }
+
+    result
+}
+
+// Arguments of a macro call whose `(` is at `chars[open]`, split at top-level commas, and the
+// index just past the closing `)`
+fn macro_arguments(chars: &[char], open: usize) -> Option<(Vec<String>, usize)> {
+    let mut args = vec![];
+    let mut current = String::new();
+    let mut depth = 0;
+    let mut i = open + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '"' | '\'' => {
+                let end = literal_end(chars, i);
+                current.extend(&chars[i..end]);
+                i = end;
+                continue;
+            },
+            '(' => depth += 1,
+            // Whitespace around an argument isn't part of it
+            ')' if depth == 0 => {
+                args.push(current.trim().to_string());
+                return Some((args, i + 1));
+            },
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                args.push(std::mem::take(&mut current).trim().to_string());
+                i += 1;
+                continue;
+            },
+            _ => {}
+        }
+        current.push(chars[i]);
+        i += 1;
+    }
+
+    None
+}
+
+// Splits a macro body into identifiers and numbers, literals, runs of whitespace, `##` and
+// single characters
+fn body_tokens(body: &str) -> Vec<String> {
+    let chars: Vec<char> = body.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i];
+        if c.is_ascii_alphanumeric() || c == '_' {
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') { i += 1 }
+        } else if c.is_whitespace() {
+            while i < chars.len() && chars[i].is_whitespace() { i += 1 }
+        } else if c == '"' || c == '\'' {
+            i = literal_end(&chars, i);
+        } else if c == '#' && chars.get(i + 1) == Some(&'#') {
+            i += 2;
+        } else {
+            i += 1;
+        }
+        tokens.push(chars[start..i].iter().collect());
+    }
+
+    tokens
+}
+
+// Macro body with parameters replaced by arguments: `#param` by the argument as a string,
+// operands of `##` by the argument as written, and other uses by the expanded argument
+fn substitute(body: &str, params: &[String], args: &[String], expanded: &[String]) -> String {
+    let tokens = body_tokens(body);
+    let param = |token: &str| params.iter().position(|param| param == token);
+    let next_token = |from: usize| (from..tokens.len()).find(|index| !tokens[*index].trim().is_empty());
+
+    let mut result = String::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = &tokens[i];
+        if token == "#" {
+            if let Some(index) = next_token(i + 1).filter(|index| param(&tokens[*index]).is_some()) {
+                let arg = args[param(&tokens[index]).unwrap()].trim();
+                result += &format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""));
+                i = index + 1;
+                continue;
+            }
+        } else if token == "##" {
+            result.truncate(result.trim_end().len());
+            if let Some(index) = next_token(i + 1) {
+                result += match param(&tokens[index]) {
+                    Some(position) => args[position].trim(),
+                    None => &tokens[index]
+                };
+                i = index + 1;
+                continue;
+            }
+        } else if let Some(position) = param(token) {
+            let pasted = next_token(i + 1).is_some_and(|index| tokens[index] == "##");
+            result += if pasted { args[position].trim() } else { &expanded[position] };
+            i += 1;
+            continue;
+        }
+
+        result += token;
+        i += 1;
+    }
+
+    result
+}
+
+// Numbers and operators of an `#if` expression once macros and `defined` are replaced.
+// Identifiers left over evaluate to 0.
+fn expression_tokens(expr: &str) -> Result<Vec<String>, String> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') { i += 1 }
+            let word: String = chars[start..i].iter().collect();
+            tokens.push(if c.is_ascii_digit() { integer_value(&word)?.to_string() } else { "0".to_string() });
+        } else if c == '\'' {
+            let end = literal_end(&chars, i);
+            let body: String = chars[i + 1..end.saturating_sub(1).max(i + 1)].iter().collect();
+            tokens.push(char_value(&body)?.to_string());
+            i = end;
+        } else {
+            let pair: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            if ["||", "&&", "==", "!=", "<=", ">=", "<<", ">>"].contains(&pair.as_str()) {
+                tokens.push(pair);
+                i += 2;
+            } else if "+-*/%<>!~&|^?:()".contains(c) {
+                tokens.push(c.to_string());
+                i += 1;
+            } else {
+                return Err(format!("unexpected '{}' in #if expression", c));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn integer_value(word: &str) -> Result<i64, String> {
+    let lower = word.to_ascii_lowercase();
+    let digits = lower.trim_end_matches(['u', 'l']);
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16)
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        u64::from_str_radix(bin, 2)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        u64::from_str_radix(&digits[1..], 8)
+    } else {
+        digits.parse::<u64>()
+    };
+
+    value.map(|value| value as i64).map_err(|_| format!("invalid integer {} in #if expression", word))
+}
+
+fn char_value(body: &str) -> Result<i64, String> {
+    let value = match body {
+        "\\n" => '\n',
+        "\\t" => '\t',
+        "\\0" => '\0',
+        "\\\\" => '\\',
+        "\\'" => '\'',
+        _ => {
+            let mut chars = body.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => return Err(format!("unsupported character constant '{}' in #if expression", body))
+            }
+        }
+    };
+
+    Ok(value as i64)
+}
+
+struct Evaluator {
+    tokens: Vec<String>,
+    pos: usize,
+    // Nesting depth of operands that aren't evaluated, such as the right side of `0 && ...`,
+    // where division by zero is not an error
+    skipping: usize
+}
+
+// Binary operators from loosest to tightest binding
+const BINARY: [&[&str]; 9] = [
+    &["||"], &["&&"], &["|"], &["^"], &["&"], &["==", "!="], &["<", ">", "<=", ">="], &["<<", ">>"], &["+", "-"]
+];
+
+impl Evaluator {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|token| token.as_str())
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        let token = self.tokens.get(self.pos).cloned().ok_or("unexpected end of #if expression")?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        match self.next()? {
+            token if token == expected => Ok(()),
+            token => Err(format!("expected '{}' in #if expression, found '{}'", expected, token))
+        }
+    }
+
+    // Parses an operand with `parse`, without evaluating it if `skip` is set
+    fn skip_if(&mut self, skip: bool, parse: impl FnOnce(&mut Self) -> Result<i64, String>) -> Result<i64, String> {
+        if skip { self.skipping += 1 }
+        let result = parse(self);
+        if skip { self.skipping -= 1 }
+        result
+    }
+
+    fn conditional(&mut self) -> Result<i64, String> {
+        let condition = self.binary(0)?;
+        if self.peek() != Some("?") { return Ok(condition) }
+
+        self.pos += 1;
+        let then = self.skip_if(condition == 0, Self::conditional)?;
+        self.expect(":")?;
+        let otherwise = self.skip_if(condition != 0, Self::conditional)?;
+        Ok(if condition != 0 { then } else { otherwise })
+    }
+
+    fn binary(&mut self, level: usize) -> Result<i64, String> {
+        if level == BINARY.len() { return self.multiplicative() }
+
+        let mut left = self.binary(level + 1)?;
+        while let Some(op) = self.peek().filter(|op| BINARY[level].contains(op)).map(|op| op.to_string()) {
+            self.pos += 1;
+            let short_circuit = (op == "||" && left != 0) || (op == "&&" && left == 0);
+            let right = self.skip_if(short_circuit, |evaluator| evaluator.binary(level + 1))?;
+            left = match op.as_str() {
+                "||" => (left != 0 || right != 0) as i64,
+                "&&" => (left != 0 && right != 0) as i64,
+                "|" => left | right,
+                "^" => left ^ right,
+                "&" => left & right,
+                "==" => (left == right) as i64,
+                "!=" => (left != right) as i64,
+                "<" => (left < right) as i64,
+                ">" => (left > right) as i64,
+                "<=" => (left <= right) as i64,
+                ">=" => (left >= right) as i64,
+                "<<" => left.wrapping_shl(right as u32),
+                ">>" => left.wrapping_shr(right as u32),
+                "+" => left.wrapping_add(right),
+                _ => left.wrapping_sub(right)
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn multiplicative(&mut self) -> Result<i64, String> {
+        let mut left = self.unary()?;
+        while let Some(op) = self.peek().filter(|op| ["*", "/", "%"].contains(op)).map(|op| op.to_string()) {
+            self.pos += 1;
+            let right = self.unary()?;
+            if op != "*" && right == 0 {
+                if self.skipping > 0 {
+                    left = 0;
+                    continue;
+                }
+                return Err("division by zero in #if expression".to_string());
+            }
+            left = match op.as_str() {
+                "*" => left.wrapping_mul(right),
+                "/" => left.wrapping_div(right),
+                _ => left.wrapping_rem(right)
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        let token = self.next()?;
+        match token.as_str() {
+            "-" => Ok(self.unary()?.wrapping_neg()),
+            "+" => self.unary(),
+            "!" => Ok((self.unary()? == 0) as i64),
+            "~" => Ok(!self.unary()?),
+            "(" => {
+                let value = self.conditional()?;
+                self.expect(")")?;
+                Ok(value)
+            },
+            _ => token.parse::<i64>().map_err(|_| format!("unexpected '{}' in #if expression", token))
+        }
+    }
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: error: {}", self.file, self.line, self.message)
+    }
+}
@@ -0,0 +1,488 @@
+use crate::{ast::*, lexer::IntSuffix};
+
+// Output of the syntax tree: `print` regenerates normalized C source, which parses back to
+// the same tree, and `dump` shows the tree itself with spans and computed types.
+
+const INDENT: &str = "    ";
+
+// Expression precedences, from the loosest binding. Binary operators other than `,` sit
+// between `CONDITIONAL` and `UNARY`.
+const COMMA: u8 = 0;
+const ASSIGN: u8 = 1;
+const CONDITIONAL: u8 = 2;
+const UNARY: u8 = 13;
+const POSTFIX: u8 = 14;
+const PRIMARY: u8 = 15;
+
+pub fn print(unit: &TranslationUnit) -> String {
+    let mut printer = Printer { out: String::new(), indent: 0 };
+    for (i, item) in unit.items.iter().enumerate() {
+        // Function definitions are set apart by blank lines
+        let function = |item: &ExternalDecl| matches!(item, ExternalDecl::Function(_));
+        if i > 0 && (function(item) || function(&unit.items[i - 1])) { printer.out.push('\n') }
+        printer.external_decl(item);
+        printer.out.push('\n');
+    }
+
+    printer.out
+}
+
+pub fn dump(unit: &TranslationUnit) -> String {
+    let mut dumper = Dumper { out: String::new(), depth: 0 };
+    dumper.line("TranslationUnit".to_string());
+    dumper.depth += 1;
+    for item in &unit.items {
+        dumper.external_decl(item);
+    }
+
+    dumper.out
+}
+
+fn storage(storage: Option<Storage>) -> &'static str {
+    match storage {
+        Some(Storage::Static) => "static ",
+        Some(Storage::Extern) => "extern ",
+        None => ""
+    }
+}
+
+// Innermost type of `ty` and the declarator that wraps `name` in the rest of it, so
+// `char *argv[2]` is `char` and `*argv[2]`
+fn declarator<'a>(mut ty: &'a Type, name: &str) -> (&'a Type, String) {
+    let mut declarator = name.to_string();
+    loop {
+        match ty {
+            Type::Pointer(inner) => {
+                declarator = format!("*{}", declarator);
+                ty = inner;
+            },
+            Type::Array(inner, len) => {
+                if declarator.starts_with('*') { declarator = format!("({})", declarator) }
+                declarator = format!("{}[{}]", declarator, len.map(|len| len.to_string()).unwrap_or_default());
+                ty = inner;
+            },
+            Type::Function { return_type, params, variadic } => {
+                if declarator.starts_with('*') { declarator = format!("({})", declarator) }
+                let params: Vec<String> = params.iter().map(|param| declaration(param, "")).collect();
+                declarator = format!("{}({})", declarator, param_list(params, *variadic));
+                ty = return_type;
+            },
+            _ => return (ty, declarator)
+        }
+    }
+}
+
+fn declaration(ty: &Type, name: &str) -> String {
+    match declarator(ty, name) {
+        (base, declarator) if declarator.is_empty() => base.to_string(),
+        (base, declarator) => format!("{} {}", base, declarator)
+    }
+}
+
+fn param_list(mut params: Vec<String>, variadic: bool) -> String {
+    if variadic { params.push("...".to_string()) }
+    if params.is_empty() { "void".to_string() } else { params.join(", ") }
+}
+
+fn signature(decl: &FunctionDecl) -> String {
+    let params = decl.params.iter().map(|param| declaration(&param.ty, param.name.as_deref().unwrap_or(""))).collect();
+    let params = if decl.prototyped { param_list(params, decl.variadic) } else { String::new() };
+    let declarator = format!("{}({})", decl.name, params);
+    format!("{}{}", storage(decl.storage), declaration(&decl.return_type, &declarator))
+}
+
+fn function_type(decl: &FunctionDecl) -> Type {
+    Type::Function {
+        return_type: Box::new(decl.return_type.clone()),
+        params: decl.params.iter().map(|param| param.ty.clone()).collect(),
+        variadic: decl.variadic
+    }
+}
+
+// Escapes `c` for a literal delimited by `quote`
+fn escape(c: char, quote: char) -> String {
+    match c {
+        '\n' => "\\n".to_string(),
+        '\t' => "\\t".to_string(),
+        '\r' => "\\r".to_string(),
+        '\\' => "\\\\".to_string(),
+        c if c == quote => format!("\\{}", c),
+        // Three digits, so a following digit can't extend the escape
+        c if c.is_ascii_control() => format!("\\{:03o}", c as u32),
+        c => c.to_string()
+    }
+}
+
+fn string_literal(text: &str) -> String {
+    format!("\"{}\"", text.chars().map(|c| escape(c, '"')).collect::<String>())
+}
+
+fn char_literal(c: char) -> String {
+    format!("'{}'", escape(c, '\''))
+}
+
+fn int_literal(value: usize, suffix: &IntSuffix) -> String {
+    format!("{}{}{}", value, if suffix.unsigned { "u" } else { "" }, if suffix.long { "l" } else { "" })
+}
+
+fn precedence(expr: &Expr) -> u8 {
+    match &expr.kind {
+        ExprKind::Binary(BinaryOp::Comma, ..) => COMMA,
+        ExprKind::Assign(..) => ASSIGN,
+        ExprKind::Conditional(..) => CONDITIONAL,
+        ExprKind::Binary(op, ..) => op.precedence() + CONDITIONAL,
+        ExprKind::Unary(UnaryOp::PostInc | UnaryOp::PostDec, _) => POSTFIX,
+        ExprKind::Unary(..) | ExprKind::SizeofExpr(_) | ExprKind::SizeofType(_) | ExprKind::Cast(..) => UNARY,
+        ExprKind::Call(..) | ExprKind::Index(..) | ExprKind::Member(..) | ExprKind::Arrow(..) => POSTFIX,
+        _ => PRIMARY
+    }
+}
+
+// `expr` as source, parenthesized unless it binds at least as tightly as `min_precedence`
+fn expr(e: &Expr, min_precedence: u8) -> String {
+    let text = match &e.kind {
+        ExprKind::Ident(name, _) => name.clone(),
+        ExprKind::Int(value, suffix) => int_literal(*value, suffix),
+        // Debug formatting always has a `.` or an exponent, so the literal stays a float
+        ExprKind::Float(value) => format!("{:?}", value),
+        ExprKind::Str(text) => string_literal(text),
+        ExprKind::Char(c) => char_literal(*c),
+        ExprKind::Unary(op @ (UnaryOp::PostInc | UnaryOp::PostDec), operand) => format!("{}{}", expr(operand, POSTFIX), op.as_str()),
+        ExprKind::Unary(op, operand) => {
+            let operand = expr(operand, UNARY);
+            // `- -a` mustn't turn into `--a`, nor `& &a` into `&&a`
+            let glued = op.as_str().ends_with(|c| "+-&".contains(c)) && operand.starts_with(op.as_str().chars().last().unwrap());
+            format!("{}{}{}", op.as_str(), if glued { " " } else { "" }, operand)
+        },
+        ExprKind::Binary(BinaryOp::Comma, left, right) => format!("{}, {}", expr(left, COMMA), expr(right, COMMA + 1)),
+        ExprKind::Binary(op, left, right) => {
+            let precedence = precedence(e);
+            format!("{} {} {}", expr(left, precedence), op.as_str(), expr(right, precedence + 1))
+        },
+        ExprKind::Assign(op, target, value) => {
+            let op = op.map(|op| op.as_str()).unwrap_or("");
+            format!("{} {}= {}", expr(target, CONDITIONAL), op, expr(value, ASSIGN))
+        },
+        ExprKind::Conditional(condition, then, otherwise) => {
+            format!("{} ? {} : {}", expr(condition, CONDITIONAL + 1), expr(then, COMMA), expr(otherwise, CONDITIONAL))
+        },
+        ExprKind::Call(callee, args) => {
+            let args: Vec<String> = args.iter().map(|arg| expr(arg, ASSIGN)).collect();
+            format!("{}({})", expr(callee, POSTFIX), args.join(", "))
+        },
+        ExprKind::Index(base, index) => format!("{}[{}]", expr(base, POSTFIX), expr(index, COMMA)),
+        // `1 .x` mustn't run together into the number `1.`
+        ExprKind::Member(base, field) if matches!(base.kind, ExprKind::Int(..) | ExprKind::Float(_)) => format!("({}).{}", expr(base, COMMA), field),
+        ExprKind::Member(base, field) => format!("{}.{}", expr(base, POSTFIX), field),
+        ExprKind::Arrow(base, field) => format!("{}->{}", expr(base, POSTFIX), field),
+        ExprKind::SizeofExpr(operand) => format!("sizeof({})", expr(operand, COMMA)),
+        ExprKind::SizeofType(ty) => format!("sizeof({})", declaration(ty, "")),
+        ExprKind::Cast(ty, operand) => format!("({}){}", declaration(ty, ""), expr(operand, UNARY))
+    };
+
+    if precedence(e) < min_precedence { format!("({})", text) } else { text }
+}
+
+fn initializer(init: &Initializer) -> String {
+    match init {
+        Initializer::Expr(e) => expr(e, ASSIGN),
+        Initializer::List(items, _) => {
+            let items: Vec<String> = items.iter().map(initializer).collect();
+            format!("{{{}}}", items.join(", "))
+        }
+    }
+}
+
+// Declaration of one or more variables sharing their specifiers, without the `;`
+fn var_decls(decls: &[VarDecl]) -> String {
+    let mut text = String::new();
+    for (i, decl) in decls.iter().enumerate() {
+        let (base, declarator) = declarator(&decl.ty, &decl.name);
+        if i == 0 {
+            text = format!("{}{}{} {}", storage(decl.storage), if decl.is_const { "const " } else { "" }, base, declarator);
+        } else {
+            text = format!("{}, {}", text, declarator);
+        }
+        if let Some(init) = &decl.init { text = format!("{} = {}", text, initializer(init)) }
+    }
+
+    text
+}
+
+struct Printer {
+    out: String,
+    indent: usize
+}
+
+impl Printer {
+    fn newline(&mut self) {
+        self.out.push('\n');
+        self.out.push_str(&INDENT.repeat(self.indent));
+    }
+
+    fn external_decl(&mut self, item: &ExternalDecl) {
+        match item {
+            ExternalDecl::Function(function) => {
+                self.out.push_str(&signature(&function.decl));
+                self.out.push(' ');
+                self.block(&function.body);
+            },
+            ExternalDecl::Prototype(decl) => self.out.push_str(&format!("{};", signature(decl))),
+            ExternalDecl::Variable(decl) => self.out.push_str(&format!("{};", var_decls(std::slice::from_ref(decl)))),
+            ExternalDecl::Struct(decl) => {
+                self.out.push_str(&format!("struct {} {{", decl.name));
+                self.indent += 1;
+                for field in &decl.fields {
+                    self.newline();
+                    self.out.push_str(&format!("{};", declaration(&field.ty, &field.name)));
+                }
+                self.indent -= 1;
+                self.newline();
+                self.out.push_str("};");
+            }
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.out.push('{');
+        self.indent += 1;
+        for stmt in &block.stmts {
+            self.newline();
+            self.stmt(stmt);
+        }
+        self.indent -= 1;
+        self.newline();
+        self.out.push('}');
+    }
+
+    // Body of a control statement: a block stays on the header's line, anything else goes on
+    // its own, indented. Returns whether the body was a block.
+    fn body(&mut self, body: &Stmt) -> bool {
+        if let StmtKind::Block(block) = &body.kind {
+            self.out.push(' ');
+            self.block(block);
+            return true;
+        }
+        self.indent += 1;
+        self.newline();
+        self.stmt(body);
+        self.indent -= 1;
+        false
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Decl(decl) => self.out.push_str(&format!("{};", var_decls(std::slice::from_ref(decl)))),
+            StmtKind::Expr(e) => self.out.push_str(&format!("{};", expr(e, COMMA))),
+            StmtKind::Block(block) => self.block(block),
+            StmtKind::Empty => self.out.push(';'),
+            StmtKind::If(condition, then, otherwise) => {
+                self.out.push_str(&format!("if ({})", expr(condition, COMMA)));
+                let block = self.body(then);
+                if let Some(otherwise) = otherwise {
+                    if block { self.out.push(' ') } else { self.newline() }
+                    self.out.push_str("else");
+                    // `else if` chains stay flat
+                    if let StmtKind::If(..) = otherwise.kind {
+                        self.out.push(' ');
+                        self.stmt(otherwise);
+                    } else {
+                        self.body(otherwise);
+                    }
+                }
+            },
+            StmtKind::While(condition, body) => {
+                self.out.push_str(&format!("while ({})", expr(condition, COMMA)));
+                self.body(body);
+            },
+            StmtKind::DoWhile(body, condition) => {
+                self.out.push_str("do");
+                if self.body(body) { self.out.push(' ') } else { self.newline() }
+                self.out.push_str(&format!("while ({});", expr(condition, COMMA)));
+            },
+            StmtKind::For { init, condition, step, body } => {
+                let init = match init {
+                    Some(ForInit::Decl(decls)) => var_decls(decls),
+                    Some(ForInit::Expr(e)) => expr(e, COMMA),
+                    None => String::new()
+                };
+                let condition = condition.as_ref().map(|e| format!(" {}", expr(e, COMMA))).unwrap_or_default();
+                let step = step.as_ref().map(|e| format!(" {}", expr(e, COMMA))).unwrap_or_default();
+                self.out.push_str(&format!("for ({};{};{})", init, condition, step));
+                self.body(body);
+            },
+            StmtKind::Break => self.out.push_str("break;"),
+            StmtKind::Continue => self.out.push_str("continue;"),
+            StmtKind::Return(None) => self.out.push_str("return;"),
+            StmtKind::Return(Some(e)) => self.out.push_str(&format!("return {};", expr(e, COMMA)))
+        }
+    }
+}
+
+struct Dumper {
+    out: String,
+    depth: usize
+}
+
+impl Dumper {
+    fn line(&mut self, text: String) {
+        self.out.push_str(&"  ".repeat(self.depth));
+        self.out.push_str(&text);
+        self.out.push('\n');
+    }
+
+    // Writes a node, then the nodes `children` writes one level deeper
+    fn node(&mut self, text: String, children: impl FnOnce(&mut Self)) {
+        self.line(text);
+        self.depth += 1;
+        children(self);
+        self.depth -= 1;
+    }
+
+    fn external_decl(&mut self, item: &ExternalDecl) {
+        match item {
+            ExternalDecl::Function(function) => {
+                self.node(function_decl("Function", &function.decl), |dumper| {
+                    dumper.params(&function.decl);
+                    dumper.block(&function.body);
+                });
+            },
+            ExternalDecl::Prototype(decl) => self.node(function_decl("Prototype", decl), |dumper| dumper.params(decl)),
+            ExternalDecl::Variable(decl) => self.var_decl(decl),
+            ExternalDecl::Struct(decl) => {
+                self.node(format!("Struct {} <{}>", decl.name, decl.span), |dumper| {
+                    for field in &decl.fields {
+                        dumper.line(format!("Field {} '{}' <{}>", field.name, field.ty, field.span));
+                    }
+                });
+            }
+        }
+    }
+
+    fn params(&mut self, decl: &FunctionDecl) {
+        for param in &decl.params {
+            let name = param.name.as_ref().map(|name| format!(" {}", name)).unwrap_or_default();
+            self.line(format!("Param{}{} '{}' <{}>", name, symbol(param.symbol), param.ty, param.span));
+        }
+    }
+
+    fn var_decl(&mut self, decl: &VarDecl) {
+        let mut text = format!("Var {}{} '{}'", decl.name, symbol(decl.symbol), decl.ty);
+        if let Some(storage) = decl.storage { text.push_str(&format!(" {:?}", storage).to_lowercase()) }
+        if decl.is_const { text.push_str(" const") }
+        self.node(format!("{} <{}>", text, decl.span), |dumper| {
+            if let Some(init) = &decl.init { dumper.initializer(init) }
+        });
+    }
+
+    fn initializer(&mut self, init: &Initializer) {
+        match init {
+            Initializer::Expr(e) => self.expr(e),
+            Initializer::List(items, span) => self.node(format!("InitList <{}>", span), |dumper| {
+                items.iter().for_each(|item| dumper.initializer(item));
+            })
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.node(format!("Block <{}>", block.span), |dumper| {
+            block.stmts.iter().for_each(|stmt| dumper.stmt(stmt));
+        });
+    }
+
+    // A missing optional child, so the positions of the others stay unambiguous
+    fn optional(&mut self, e: Option<&Expr>) {
+        match e {
+            Some(e) => self.expr(e),
+            None => self.line("None".to_string())
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        let span = stmt.span;
+        match &stmt.kind {
+            StmtKind::Decl(decl) => self.var_decl(decl),
+            StmtKind::Expr(e) => self.expr(e),
+            StmtKind::Block(block) => self.block(block),
+            StmtKind::Empty => self.line(format!("Empty <{}>", span)),
+            StmtKind::If(condition, then, otherwise) => self.node(format!("If <{}>", span), |dumper| {
+                dumper.expr(condition);
+                dumper.stmt(then);
+                if let Some(otherwise) = otherwise { dumper.stmt(otherwise) }
+            }),
+            StmtKind::While(condition, body) => self.node(format!("While <{}>", span), |dumper| {
+                dumper.expr(condition);
+                dumper.stmt(body);
+            }),
+            StmtKind::DoWhile(body, condition) => self.node(format!("DoWhile <{}>", span), |dumper| {
+                dumper.stmt(body);
+                dumper.expr(condition);
+            }),
+            StmtKind::For { init, condition, step, body } => self.node(format!("For <{}>", span), |dumper| {
+                match init {
+                    Some(ForInit::Decl(decls)) => decls.iter().for_each(|decl| dumper.var_decl(decl)),
+                    Some(ForInit::Expr(e)) => dumper.expr(e),
+                    None => dumper.line("None".to_string())
+                }
+                dumper.optional(condition.as_ref());
+                dumper.optional(step.as_ref());
+                dumper.stmt(body);
+            }),
+            StmtKind::Break => self.line(format!("Break <{}>", span)),
+            StmtKind::Continue => self.line(format!("Continue <{}>", span)),
+            StmtKind::Return(value) => self.node(format!("Return <{}>", span), |dumper| {
+                if let Some(value) = value { dumper.expr(value) }
+            })
+        }
+    }
+
+    fn expr(&mut self, e: &Expr) {
+        let label = match &e.kind {
+            ExprKind::Ident(name, id) => format!("Ident {}{}", name, symbol(*id)),
+            ExprKind::Int(value, suffix) => format!("Int {}", int_literal(*value, suffix)),
+            ExprKind::Float(value) => format!("Float {:?}", value),
+            ExprKind::Str(text) => format!("Str {}", string_literal(text)),
+            ExprKind::Char(c) => format!("Char {}", char_literal(*c)),
+            ExprKind::Unary(op, _) => format!("Unary {:?}", op),
+            ExprKind::Binary(op, ..) => format!("Binary {}", op.as_str()),
+            ExprKind::Assign(op, ..) => format!("Assign {}=", op.map(|op| op.as_str()).unwrap_or("")),
+            ExprKind::Conditional(..) => "Conditional".to_string(),
+            ExprKind::Call(..) => "Call".to_string(),
+            ExprKind::Index(..) => "Index".to_string(),
+            ExprKind::Member(_, field) => format!("Member .{}", field),
+            ExprKind::Arrow(_, field) => format!("Member ->{}", field),
+            ExprKind::SizeofExpr(_) => "Sizeof".to_string(),
+            ExprKind::SizeofType(ty) => format!("Sizeof '{}'", ty),
+            ExprKind::Cast(ty, _) => format!("Cast '{}'", ty)
+        };
+        let ty = e.ty.as_ref().map(|ty| format!(" : '{}'", ty)).unwrap_or_default();
+
+        self.node(format!("{}{} <{}>", label, ty, e.span), |dumper| match &e.kind {
+            ExprKind::Ident(..) | ExprKind::Int(..) | ExprKind::Float(_) | ExprKind::Str(_) | ExprKind::Char(_) | ExprKind::SizeofType(_) => {},
+            ExprKind::Unary(_, operand) | ExprKind::Member(operand, _) | ExprKind::Arrow(operand, _) |
+            ExprKind::SizeofExpr(operand) | ExprKind::Cast(_, operand) => dumper.expr(operand),
+            ExprKind::Binary(_, left, right) | ExprKind::Assign(_, left, right) | ExprKind::Index(left, right) => {
+                dumper.expr(left);
+                dumper.expr(right);
+            },
+            ExprKind::Conditional(condition, then, otherwise) => {
+                dumper.expr(condition);
+                dumper.expr(then);
+                dumper.expr(otherwise);
+            },
+            ExprKind::Call(callee, args) => {
+                dumper.expr(callee);
+                args.iter().for_each(|arg| dumper.expr(arg));
+            }
+        });
+    }
+}
+
+fn function_decl(label: &str, decl: &FunctionDecl) -> String {
+    let storage = decl.storage.map(|storage| format!(" {:?}", storage).to_lowercase()).unwrap_or_default();
+    format!("{} {}{} '{}'{} <{}>", label, decl.name, symbol(decl.symbol), function_type(decl), storage, decl.span)
+}
+
+fn symbol(id: Option<SymbolId>) -> String {
+    id.map(|id| format!(" #{}", id.0)).unwrap_or_default()
+}
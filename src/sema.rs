@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+
+use crate::{ast::*, diagnostic::Diagnostic, lexer::Span};
+
+// Name resolution: every declaration gets a symbol, every identifier is resolved to the symbol
+// it names, and the `symbol` fields of the AST are filled in with their IDs.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SymbolKind {
+    Global { is_extern: bool, initialized: bool },
+    Local,
+    Param,
+    // Calls to functions declared with `()` can't be checked against their parameters
+    Function { defined: bool, prototyped: bool }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub ty: Type,
+    pub span: Span
+}
+
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    pub symbols: Vec<Symbol>
+}
+
+impl SymbolTable {
+    pub fn get(&self, id: SymbolId) -> &Symbol {
+        &self.symbols[id.0]
+    }
+}
+
+struct Analyzer {
+    table: SymbolTable,
+    // Innermost scope last; the first one is file scope
+    scopes: Vec<HashMap<String, SymbolId>>,
+    diagnostics: Vec<Diagnostic>
+}
+
+// Resolves the names in `unit`, returning the symbols it declares and the problems found
+pub fn analyze(unit: &mut TranslationUnit) -> (SymbolTable, Vec<Diagnostic>) {
+    let mut analyzer = Analyzer { table: SymbolTable::default(), scopes: vec![HashMap::new()], diagnostics: vec![] };
+    for item in &mut unit.items {
+        analyzer.external_decl(item);
+    }
+
+    (analyzer.table, analyzer.diagnostics)
+}
+
+// Parameters declared as arrays are pointers to the element type
+fn param_type(ty: &Type) -> Type {
+    match ty {
+        Type::Array(element, _) => Type::Pointer(element.clone()),
+        ty => ty.clone()
+    }
+}
+
+impl Analyzer {
+    fn lookup(&self, name: &str) -> Option<SymbolId> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name).copied())
+    }
+
+    fn add(&mut self, name: &str, kind: SymbolKind, ty: Type, span: Span) -> SymbolId {
+        let id = SymbolId(self.table.symbols.len());
+        self.table.symbols.push(Symbol { name: name.to_string(), kind, ty, span });
+        self.scopes.last_mut().unwrap().insert(name.to_string(), id);
+        id
+    }
+
+    // Declares a block-scoped variable or parameter
+    fn declare_local(&mut self, name: &str, kind: SymbolKind, ty: Type, span: Span) -> SymbolId {
+        if self.scopes.last().unwrap().contains_key(name) {
+            self.diagnostics.push(Diagnostic::error(format!("redeclaration of '{}'", name), span));
+        } else if self.lookup(name).is_some() {
+            self.diagnostics.push(Diagnostic::warning(format!("declaration of '{}' shadows a previous declaration", name), span));
+        }
+
+        self.add(name, kind, ty, span)
+    }
+
+    // Declares a function or global variable. File-scope names may be declared more than once
+    // as long as the declarations agree and at most one of them is a definition.
+    fn declare_global(&mut self, name: &str, kind: SymbolKind, ty: Type, span: Span) -> SymbolId {
+        let id = match self.scopes[0].get(name) {
+            Some(id) => *id,
+            None => return self.add(name, kind, ty, span)
+        };
+
+        let previous = self.table.get(id);
+        let error = match (&previous.kind, &kind) {
+            (SymbolKind::Function { defined, prototyped }, SymbolKind::Function { defined: new_defined, prototyped: new_prototyped }) => {
+                // Without a prototype on both sides only the return types have to agree
+                let compatible = match (&previous.ty, &ty) {
+                    (Type::Function { return_type, .. }, Type::Function { return_type: new_return_type, .. }) if !(*prototyped && *new_prototyped) => return_type == new_return_type,
+                    _ => previous.ty == ty
+                };
+                if !compatible {
+                    Some(format!("conflicting types for '{}'", name))
+                } else if *defined && *new_defined {
+                    Some(format!("redefinition of '{}'", name))
+                } else {
+                    None
+                }
+            },
+            (SymbolKind::Global { is_extern, initialized }, SymbolKind::Global { is_extern: new_extern, initialized: new_initialized }) => {
+                if previous.ty != ty {
+                    Some(format!("conflicting types for '{}'", name))
+                } else if *initialized && *new_initialized && !is_extern && !new_extern {
+                    Some(format!("redefinition of '{}'", name))
+                } else {
+                    None
+                }
+            },
+            _ => Some(format!("redeclaration of '{}' as a different kind of symbol", name))
+        };
+
+        if let Some(message) = error {
+            self.diagnostics.push(Diagnostic::error(message, span));
+            return id;
+        }
+
+        // Remember a definition, the parameters of the first prototype, or the initializer of a
+        // tentative definition
+        let symbol = &mut self.table.symbols[id.0];
+        match (&mut symbol.kind, kind) {
+            (SymbolKind::Function { defined, prototyped }, SymbolKind::Function { defined: new_defined, prototyped: new_prototyped }) => {
+                if new_defined {
+                    *defined = true;
+                    symbol.span = span;
+                }
+                if new_prototyped && !*prototyped {
+                    *prototyped = true;
+                    symbol.ty = ty;
+                }
+            },
+            (SymbolKind::Global { is_extern, initialized }, SymbolKind::Global { is_extern: false, initialized: new_initialized }) => {
+                *is_extern = false;
+                *initialized |= new_initialized;
+            },
+            _ => {}
+        }
+        id
+    }
+
+    fn external_decl(&mut self, item: &mut ExternalDecl) {
+        match item {
+            ExternalDecl::Function(function) => {
+                let id = self.declare_function(&function.decl, true);
+                function.decl.symbol = Some(id);
+
+                // Parameters share the scope of the body's outermost block
+                self.scopes.push(HashMap::new());
+                for param in &mut function.decl.params {
+                    match &param.name {
+                        Some(name) => param.symbol = Some(self.declare_local(name, SymbolKind::Param, param_type(&param.ty), param.span)),
+                        None => self.diagnostics.push(Diagnostic::error("parameter name omitted in function definition".to_string(), param.span))
+                    }
+                }
+                for stmt in &mut function.body.stmts {
+                    self.stmt(stmt);
+                }
+                self.scopes.pop();
+            },
+            ExternalDecl::Prototype(decl) => {
+                let id = self.declare_function(decl, false);
+                decl.symbol = Some(id);
+            },
+            ExternalDecl::Variable(decl) => {
+                let kind = SymbolKind::Global { is_extern: decl.storage == Some(Storage::Extern), initialized: decl.init.is_some() };
+                decl.symbol = Some(self.declare_global(&decl.name, kind, decl.ty.clone(), decl.span));
+                if let Some(init) = &mut decl.init { self.initializer(init) }
+            },
+            ExternalDecl::Struct(_) => {}
+        }
+    }
+
+    fn declare_function(&mut self, decl: &FunctionDecl, defined: bool) -> SymbolId {
+        let ty = Type::Function {
+            return_type: Box::new(decl.return_type.clone()),
+            params: decl.params.iter().map(|param| param_type(&param.ty)).collect(),
+            variadic: decl.variadic
+        };
+        self.declare_global(&decl.name, SymbolKind::Function { defined, prototyped: decl.prototyped }, ty, decl.span)
+    }
+
+    fn local_decl(&mut self, decl: &mut VarDecl) {
+        // The variable is in scope in its own initializer
+        decl.symbol = Some(match decl.storage {
+            Some(Storage::Extern) => self.local_extern(decl),
+            _ => self.declare_local(&decl.name, SymbolKind::Local, decl.ty.clone(), decl.span)
+        });
+        if let Some(init) = &mut decl.init { self.initializer(init) }
+    }
+
+    // A block-scope `extern` declaration names the file-scope variable, and has to agree with
+    // the declarations of it there
+    fn local_extern(&mut self, decl: &VarDecl) -> SymbolId {
+        let local = self.scopes.last().unwrap().get(&decl.name).copied();
+        let id = match local {
+            Some(id) if !matches!(self.table.get(id).kind, SymbolKind::Global { .. }) => {
+                self.diagnostics.push(Diagnostic::error(format!("redeclaration of '{}'", decl.name), decl.span));
+                return id;
+            },
+            _ => self.declare_global(&decl.name, SymbolKind::Global { is_extern: true, initialized: false }, decl.ty.clone(), decl.span)
+        };
+        self.scopes.last_mut().unwrap().insert(decl.name.clone(), id);
+        id
+    }
+
+    fn initializer(&mut self, init: &mut Initializer) {
+        match init {
+            Initializer::Expr(expr) => self.expr(expr),
+            Initializer::List(items, _) => items.iter_mut().for_each(|item| self.initializer(item))
+        }
+    }
+
+    fn block(&mut self, block: &mut Block) {
+        self.scopes.push(HashMap::new());
+        for stmt in &mut block.stmts {
+            self.stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    fn stmt(&mut self, stmt: &mut Stmt) {
+        match &mut stmt.kind {
+            StmtKind::Decl(decl) => self.local_decl(decl),
+            StmtKind::Expr(expr) => self.expr(expr),
+            StmtKind::Block(block) => self.block(block),
+            StmtKind::Empty | StmtKind::Break | StmtKind::Continue => {},
+            StmtKind::If(condition, then, otherwise) => {
+                self.expr(condition);
+                self.stmt(then);
+                if let Some(otherwise) = otherwise { self.stmt(otherwise) }
+            },
+            StmtKind::While(condition, body) => {
+                self.expr(condition);
+                self.stmt(body);
+            },
+            StmtKind::DoWhile(body, condition) => {
+                self.stmt(body);
+                self.expr(condition);
+            },
+            StmtKind::For { init, condition, step, body } => {
+                self.scopes.push(HashMap::new());
+                match init {
+                    Some(ForInit::Decl(decls)) => decls.iter_mut().for_each(|decl| self.local_decl(decl)),
+                    Some(ForInit::Expr(expr)) => self.expr(expr),
+                    None => {}
+                }
+                if let Some(condition) = condition { self.expr(condition) }
+                if let Some(step) = step { self.expr(step) }
+                self.stmt(body);
+                self.scopes.pop();
+            },
+            StmtKind::Return(value) => {
+                if let Some(value) = value { self.expr(value) }
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        match &mut expr.kind {
+            ExprKind::Ident(name, symbol) => {
+                *symbol = self.lookup(name);
+                if symbol.is_none() {
+                    self.diagnostics.push(Diagnostic::error(format!("use of undeclared identifier '{}'", name), expr.span));
+                }
+            },
+            ExprKind::Int(..) | ExprKind::Float(_) | ExprKind::Str(_) | ExprKind::Char(_) | ExprKind::SizeofType(_) => {},
+            ExprKind::Unary(_, operand) | ExprKind::SizeofExpr(operand) | ExprKind::Cast(_, operand) |
+            ExprKind::Member(operand, _) | ExprKind::Arrow(operand, _) => self.expr(operand),
+            ExprKind::Binary(_, left, right) | ExprKind::Assign(_, left, right) | ExprKind::Index(left, right) => {
+                self.expr(left);
+                self.expr(right);
+            },
+            ExprKind::Conditional(condition, then, otherwise) => {
+                self.expr(condition);
+                self.expr(then);
+                self.expr(otherwise);
+            },
+            ExprKind::Call(callee, args) => {
+                self.call(callee, args.len(), expr.span);
+                args.iter_mut().for_each(|arg| self.expr(arg));
+            }
+        }
+    }
+
+    // Resolves the function called with `count` arguments and checks the number of arguments
+    fn call(&mut self, callee: &mut Expr, count: usize, span: Span) {
+        let (name, symbol) = match &mut callee.kind {
+            ExprKind::Ident(name, symbol) => (name, symbol),
+            _ => return self.expr(callee)
+        };
+
+        *symbol = self.lookup(name);
+        let id = match symbol {
+            Some(id) => *id,
+            None => return self.diagnostics.push(Diagnostic::error(format!("call to undeclared function '{}'", name), callee.span))
+        };
+        let symbol = self.table.get(id);
+        if let (SymbolKind::Function { prototyped: true, .. }, Type::Function { params, variadic, .. }) = (&symbol.kind, &symbol.ty) {
+            if count != params.len() && !(*variadic && count > params.len()) {
+                let expected = if *variadic { format!("at least {}", params.len()) } else { params.len().to_string() };
+                let plural = if params.len() == 1 { "" } else { "s" };
+                self.diagnostics.push(Diagnostic::error(format!("function '{}' expects {} argument{}, found {}", name, expected, plural, count), span));
+            }
+        }
+    }
+}
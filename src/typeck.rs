@@ -0,0 +1,484 @@
+use std::collections::HashMap;
+
+use crate::{ast::*, diagnostic::Diagnostic, lexer::{IntSuffix, Span}, sema::{SymbolKind, SymbolTable}};
+
+// Type checking of a resolved translation unit: computes the type of every expression and
+// reports operands, conversions and statements the C rules don't allow.
+
+const INT: Type = Type::Int { kind: IntKind::Int, unsigned: false };
+// Type of `sizeof`, and of the difference of two pointers
+const SIZE: Type = Type::Int { kind: IntKind::Long, unsigned: true };
+const PTRDIFF: Type = Type::Int { kind: IntKind::Long, unsigned: false };
+
+struct Checker<'a> {
+    symbols: &'a SymbolTable,
+    structs: HashMap<String, Vec<Field>>,
+    // Function whose body is being checked, for `return`
+    function: String,
+    return_type: Type,
+    diagnostics: Vec<Diagnostic>
+}
+
+// Annotates every expression in `unit` with its type, returning the type errors found. Names
+// must already have been resolved by `sema::analyze`.
+pub fn check(unit: &mut TranslationUnit, symbols: &SymbolTable) -> Vec<Diagnostic> {
+    let structs = unit.items.iter().filter_map(|item| match item {
+        ExternalDecl::Struct(decl) => Some((decl.name.clone(), decl.fields.clone())),
+        _ => None
+    }).collect();
+    let mut checker = Checker { symbols, structs, function: String::new(), return_type: Type::Void, diagnostics: vec![] };
+
+    for item in &mut unit.items {
+        match item {
+            ExternalDecl::Function(function) => {
+                checker.function = function.decl.name.clone();
+                checker.return_type = function.decl.return_type.clone();
+                for stmt in &mut function.body.stmts {
+                    checker.stmt(stmt);
+                }
+            },
+            ExternalDecl::Variable(decl) => checker.var_decl(decl),
+            ExternalDecl::Prototype(_) | ExternalDecl::Struct(_) => {}
+        }
+    }
+
+    checker.diagnostics
+}
+
+// Arrays and functions used as values turn into pointers to their first element or themselves
+fn decay(ty: Type) -> Type {
+    match ty {
+        Type::Array(element, _) => Type::Pointer(element),
+        Type::Function { .. } => Type::Pointer(Box::new(ty)),
+        ty => ty
+    }
+}
+
+// Integer promotions: anything smaller than `int` is computed as an `int`
+fn promote(ty: &Type) -> Type {
+    match ty {
+        Type::Int { kind: IntKind::Char | IntKind::Short, .. } => INT,
+        ty => ty.clone()
+    }
+}
+
+// Usual arithmetic conversions: the common type two arithmetic operands are converted to
+fn common_type(left: &Type, right: &Type) -> Type {
+    match (promote(left), promote(right)) {
+        (Type::Double, _) | (_, Type::Double) => Type::Double,
+        (Type::Float, _) | (_, Type::Float) => Type::Float,
+        (Type::Int { kind: left_kind, unsigned: left_unsigned }, Type::Int { kind: right_kind, unsigned: right_unsigned }) => {
+            let long = left_kind == IntKind::Long || right_kind == IntKind::Long;
+            let kind = if long { IntKind::Long } else { IntKind::Int };
+            // A signed `long` holds every `unsigned int`, so only equal ranks make the result unsigned
+            let unsigned = match (left_kind == right_kind, left_unsigned, right_unsigned) {
+                (true, left, right) => left || right,
+                (false, _, _) => if left_kind == IntKind::Long { left_unsigned } else { right_unsigned }
+            };
+            Type::Int { kind, unsigned }
+        },
+        (left, _) => left
+    }
+}
+
+// Integer constant zero, optionally cast to `void *`
+fn is_null(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Int(0, _) => true,
+        ExprKind::Cast(Type::Pointer(ty), operand) => **ty == Type::Void && is_null(operand),
+        _ => false
+    }
+}
+
+fn literal_type(suffix: &IntSuffix) -> Type {
+    Type::Int { kind: if suffix.long { IntKind::Long } else { IntKind::Int }, unsigned: suffix.unsigned }
+}
+
+impl Checker<'_> {
+    fn error(&mut self, message: String, span: Span) -> Option<Type> {
+        self.diagnostics.push(Diagnostic::error(message, span));
+        None
+    }
+
+    fn is_lvalue(&self, expr: &Expr) -> bool {
+        match &expr.kind {
+            ExprKind::Ident(_, Some(id)) => !matches!(self.symbols.get(*id).kind, SymbolKind::Function { .. }),
+            ExprKind::Unary(UnaryOp::Deref, _) | ExprKind::Index(..) | ExprKind::Arrow(..) | ExprKind::Str(_) => true,
+            ExprKind::Member(base, _) => self.is_lvalue(base),
+            _ => false
+        }
+    }
+
+    // Checks that `expr`, of type `ty`, can be assigned to or incremented
+    fn modifiable(&mut self, expr: &Expr, ty: &Type) -> bool {
+        if !self.is_lvalue(expr) {
+            self.error("expression is not assignable".to_string(), expr.span);
+            false
+        } else if let Type::Array(..) = ty {
+            self.error(format!("array type '{}' is not assignable", ty), expr.span);
+            false
+        } else {
+            true
+        }
+    }
+
+    // Checks that a value of type `ty` can be converted to `target` as if by assignment; `what`
+    // describes the conversion in messages
+    fn convert(&mut self, target: &Type, value: &Expr, ty: &Type, what: &str) {
+        let warning = match (target, ty) {
+            (target, ty) if target.is_arithmetic() && ty.is_arithmetic() => return,
+            (Type::Struct(target), Type::Struct(ty)) if target == ty => return,
+            (Type::Pointer(_), _) if ty.is_integer() && is_null(value) => return,
+            (Type::Pointer(target_pointee), Type::Pointer(pointee)) => {
+                if target_pointee == pointee || **target_pointee == Type::Void || **pointee == Type::Void { return }
+                "incompatible pointer types"
+            },
+            (Type::Pointer(_), ty) if ty.is_integer() => "incompatible integer to pointer conversion",
+            (target, Type::Pointer(_)) if target.is_integer() => "incompatible pointer to integer conversion",
+            _ => {
+                self.error(format!("{} '{}' from incompatible type '{}'", what, target, ty), value.span);
+                return;
+            }
+        };
+        self.diagnostics.push(Diagnostic::warning(format!("{} {} '{}' from '{}'", warning, what, target, ty), value.span));
+    }
+
+    fn complete(&mut self, ty: &Type, span: Span) -> bool {
+        let incomplete = match ty {
+            Type::Void => true,
+            Type::Struct(name) => !self.structs.contains_key(name),
+            _ => false
+        };
+        if incomplete { self.error(format!("variable has incomplete type '{}'", ty), span); }
+        !incomplete
+    }
+
+    fn var_decl(&mut self, decl: &mut VarDecl) {
+        if decl.storage != Some(Storage::Extern) && !self.complete(&decl.ty, decl.span) { return }
+        if let Some(init) = &mut decl.init {
+            self.initializer(&decl.ty, init);
+        }
+    }
+
+    fn initializer(&mut self, ty: &Type, init: &mut Initializer) {
+        match (ty, init) {
+            (Type::Array(element, _), Initializer::Expr(expr)) => {
+                let string = matches!(**element, Type::Int { kind: IntKind::Char, .. }) && matches!(expr.kind, ExprKind::Str(_));
+                if self.expr(expr).is_some() && !string {
+                    self.error("array initializer must be an initializer list".to_string(), expr.span);
+                }
+            },
+            (ty, Initializer::Expr(expr)) => {
+                if let Some(value) = self.value(expr) { self.convert(ty, expr, &value, "initializing") }
+            },
+            (Type::Array(element, len), Initializer::List(items, span)) => {
+                if len.is_some_and(|len| items.len() > len) {
+                    self.diagnostics.push(Diagnostic::warning("excess elements in array initializer".to_string(), *span));
+                }
+                items.iter_mut().for_each(|item| self.initializer(element, item));
+            },
+            (Type::Struct(name), Initializer::List(items, span)) => {
+                let fields = self.structs.get(name).cloned().unwrap_or_default();
+                if items.len() > fields.len() {
+                    self.diagnostics.push(Diagnostic::warning("excess elements in struct initializer".to_string(), *span));
+                }
+                for (field, item) in fields.iter().zip(items) {
+                    self.initializer(&field.ty, item);
+                }
+            },
+            (ty, Initializer::List(items, span)) => {
+                if items.len() > 1 {
+                    self.diagnostics.push(Diagnostic::warning("excess elements in scalar initializer".to_string(), *span));
+                }
+                if let Some(item) = items.first_mut() { self.initializer(ty, item) }
+            }
+        }
+    }
+
+    fn stmt(&mut self, stmt: &mut Stmt) {
+        match &mut stmt.kind {
+            StmtKind::Decl(decl) => self.var_decl(decl),
+            StmtKind::Expr(expr) => { self.expr(expr); },
+            StmtKind::Block(block) => block.stmts.iter_mut().for_each(|stmt| self.stmt(stmt)),
+            StmtKind::Empty | StmtKind::Break | StmtKind::Continue => {},
+            StmtKind::If(condition, then, otherwise) => {
+                self.condition(condition);
+                self.stmt(then);
+                if let Some(otherwise) = otherwise { self.stmt(otherwise) }
+            },
+            StmtKind::While(condition, body) => {
+                self.condition(condition);
+                self.stmt(body);
+            },
+            StmtKind::DoWhile(body, condition) => {
+                self.stmt(body);
+                self.condition(condition);
+            },
+            StmtKind::For { init, condition, step, body } => {
+                match init {
+                    Some(ForInit::Decl(decls)) => decls.iter_mut().for_each(|decl| self.var_decl(decl)),
+                    Some(ForInit::Expr(expr)) => { self.expr(expr); },
+                    None => {}
+                }
+                if let Some(condition) = condition { self.condition(condition) }
+                if let Some(step) = step { self.expr(step); }
+                self.stmt(body);
+            },
+            StmtKind::Return(value) => self.return_value(value.as_mut(), stmt.span)
+        }
+    }
+
+    fn return_value(&mut self, value: Option<&mut Expr>, span: Span) {
+        match value {
+            Some(value) if self.return_type == Type::Void => {
+                self.expr(value);
+                self.error(format!("void function '{}' should not return a value", self.function), span);
+            },
+            Some(value) => {
+                if let Some(ty) = self.value(value) {
+                    let return_type = self.return_type.clone();
+                    self.convert(&return_type, value, &ty, "returning");
+                }
+            },
+            None if self.return_type != Type::Void => {
+                self.error(format!("non-void function '{}' should return a value", self.function), span);
+            },
+            None => {}
+        }
+    }
+
+    fn condition(&mut self, condition: &mut Expr) {
+        if let Some(ty) = self.value(condition) {
+            if !ty.is_scalar() {
+                self.error(format!("statement requires expression of scalar type ('{}' invalid)", ty), condition.span);
+            }
+        }
+    }
+
+    // Computes and records the type of `expr`
+    fn expr(&mut self, expr: &mut Expr) -> Option<Type> {
+        let ty = self.compute(expr);
+        expr.ty = ty.clone();
+        ty
+    }
+
+    // Type of `expr` used as a value, after arrays and functions decay to pointers
+    fn rvalue(&mut self, expr: &mut Expr) -> Option<Type> {
+        self.expr(expr).map(decay)
+    }
+
+    // Like `rvalue`, but `void` isn't allowed
+    fn value(&mut self, expr: &mut Expr) -> Option<Type> {
+        match self.rvalue(expr)? {
+            Type::Void => self.error("void value not ignored as it ought to be".to_string(), expr.span),
+            ty => Some(ty)
+        }
+    }
+
+    fn compute(&mut self, expr: &mut Expr) -> Option<Type> {
+        let span = expr.span;
+        match &mut expr.kind {
+            ExprKind::Ident(_, symbol) => symbol.map(|id| self.symbols.get(id).ty.clone()),
+            ExprKind::Int(_, suffix) => Some(literal_type(suffix)),
+            ExprKind::Float(_) => Some(Type::Double),
+            ExprKind::Char(_) => Some(INT),
+            ExprKind::Str(text) => Some(Type::Array(Box::new(Type::Int { kind: IntKind::Char, unsigned: false }), Some(text.len() + 1))),
+            ExprKind::Unary(op, operand) => self.unary(*op, operand, span),
+            ExprKind::Binary(BinaryOp::Comma, left, right) => {
+                self.expr(left);
+                self.rvalue(right)
+            },
+            ExprKind::Binary(op, left, right) => {
+                let (left_ty, right_ty) = (self.value(left), self.value(right));
+                self.binary(*op, left, &left_ty?, right, &right_ty?, span)
+            },
+            ExprKind::Assign(op, target, value) => {
+                let (target_ty, value_ty) = (self.expr(target), self.value(value));
+                let (target_ty, value_ty) = (target_ty?, value_ty?);
+                if !self.modifiable(target, &target_ty) { return None }
+                // `a op= b` stores the result of `a op b` back into `a`
+                let value_ty = match op {
+                    Some(op) => self.binary(*op, target, &target_ty, value, &value_ty, span)?,
+                    None => value_ty
+                };
+                self.convert(&target_ty, value, &value_ty, "assigning to");
+                Some(target_ty)
+            },
+            ExprKind::Conditional(condition, then, otherwise) => {
+                self.condition(condition);
+                let (then_ty, otherwise_ty) = (self.rvalue(then), self.rvalue(otherwise));
+                self.conditional(then, &then_ty?, otherwise, &otherwise_ty?, span)
+            },
+            ExprKind::Call(callee, args) => self.call(callee, args),
+            ExprKind::Index(base, index) => {
+                let (base_ty, index_ty) = (self.value(base), self.value(index));
+                match (base_ty?, index_ty?) {
+                    (Type::Pointer(element), index) | (index, Type::Pointer(element)) if index.is_integer() => Some(*element),
+                    (Type::Pointer(_), _) | (_, Type::Pointer(_)) => self.error("array subscript is not an integer".to_string(), span),
+                    _ => self.error("subscripted value is not an array or pointer".to_string(), span)
+                }
+            },
+            ExprKind::Member(base, name) => {
+                let ty = self.expr(base)?;
+                self.member(&ty, name, base.span, span)
+            },
+            ExprKind::Arrow(base, name) => match self.value(base)? {
+                Type::Pointer(ty) => self.member(&ty, name, base.span, span),
+                ty => self.error(format!("member reference type '{}' is not a pointer", ty), base.span)
+            },
+            ExprKind::SizeofExpr(operand) => {
+                let ty = self.expr(operand)?;
+                self.sizeof(&ty, span)
+            },
+            ExprKind::SizeofType(ty) => {
+                let ty = ty.clone();
+                self.sizeof(&ty, span)
+            },
+            ExprKind::Cast(target, operand) => {
+                let target = target.clone();
+                let ty = self.value(operand)?;
+                if target != Type::Void && !(target.is_scalar() && ty.is_scalar()) {
+                    let invalid = if target.is_scalar() { &ty } else { &target };
+                    return self.error(format!("cannot cast to '{}' from '{}': '{}' is not an arithmetic or pointer type", target, ty, invalid), span);
+                }
+                Some(target)
+            }
+        }
+    }
+
+    fn unary(&mut self, op: UnaryOp, operand: &mut Expr, span: Span) -> Option<Type> {
+        match op {
+            UnaryOp::AddrOf => {
+                let ty = self.expr(operand)?;
+                if !self.is_lvalue(operand) && !matches!(ty, Type::Function { .. }) {
+                    return self.error(format!("cannot take the address of an rvalue of type '{}'", ty), span);
+                }
+                Some(Type::Pointer(Box::new(ty)))
+            },
+            UnaryOp::Deref => match self.value(operand)? {
+                Type::Pointer(ty) => Some(*ty),
+                ty => self.error(format!("indirection requires pointer operand ('{}' invalid)", ty), span)
+            },
+            UnaryOp::PreInc | UnaryOp::PreDec | UnaryOp::PostInc | UnaryOp::PostDec => {
+                let ty = self.expr(operand)?;
+                if !self.modifiable(operand, &ty) { return None }
+                if !ty.is_scalar() { return self.error(format!("cannot increment or decrement value of type '{}'", ty), span) }
+                Some(ty)
+            },
+            UnaryOp::Neg | UnaryOp::Plus | UnaryOp::BitNot | UnaryOp::Not => {
+                let ty = self.value(operand)?;
+                let valid = match op {
+                    UnaryOp::BitNot => ty.is_integer(),
+                    UnaryOp::Not => ty.is_scalar(),
+                    _ => ty.is_arithmetic()
+                };
+                if !valid { return self.error(format!("invalid argument type '{}' to unary expression", ty), span) }
+                Some(if op == UnaryOp::Not { INT } else { promote(&ty) })
+            }
+        }
+    }
+
+    // Type of `left op right` given the types of its operands, which have already decayed
+    fn binary(&mut self, op: BinaryOp, left: &Expr, left_ty: &Type, right: &Expr, right_ty: &Type, span: Span) -> Option<Type> {
+        let ty = match (op, left_ty, right_ty) {
+            (BinaryOp::Mul | BinaryOp::Div | BinaryOp::Add | BinaryOp::Sub, left, right) if left.is_arithmetic() && right.is_arithmetic() => {
+                Some(common_type(left, right))
+            },
+            (BinaryOp::Mod | BinaryOp::BitAnd | BinaryOp::BitXor | BinaryOp::BitOr, left, right) if left.is_integer() && right.is_integer() => {
+                Some(common_type(left, right))
+            },
+            (BinaryOp::Shl | BinaryOp::Shr, left, right) if left.is_integer() && right.is_integer() => Some(promote(left)),
+            (BinaryOp::Add, Type::Pointer(_), offset) | (BinaryOp::Sub, Type::Pointer(_), offset) if offset.is_integer() => Some(left_ty.clone()),
+            (BinaryOp::Add, offset, Type::Pointer(_)) if offset.is_integer() => Some(right_ty.clone()),
+            (BinaryOp::Sub, Type::Pointer(left), Type::Pointer(right)) if left == right => Some(PTRDIFF),
+            (BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Le | BinaryOp::Ge | BinaryOp::Eq | BinaryOp::Ne, _, _) => {
+                let comparable = match (left_ty, right_ty) {
+                    (left_ty, right_ty) if left_ty.is_arithmetic() && right_ty.is_arithmetic() => true,
+                    (Type::Pointer(left_pointee), Type::Pointer(right_pointee)) => {
+                        left_pointee == right_pointee ||
+                            (matches!(op, BinaryOp::Eq | BinaryOp::Ne) && (**left_pointee == Type::Void || **right_pointee == Type::Void))
+                    },
+                    (Type::Pointer(_), _) => matches!(op, BinaryOp::Eq | BinaryOp::Ne) && is_null(right),
+                    (_, Type::Pointer(_)) => matches!(op, BinaryOp::Eq | BinaryOp::Ne) && is_null(left),
+                    _ => false
+                };
+                comparable.then_some(INT)
+            },
+            (BinaryOp::And | BinaryOp::Or, left, right) if left.is_scalar() && right.is_scalar() => Some(INT),
+            _ => None
+        };
+
+        if ty.is_none() {
+            self.error(format!("invalid operands to binary expression ('{}' and '{}')", left_ty, right_ty), span);
+        }
+        ty
+    }
+
+    // Common type of the branches of `condition ? then : otherwise`
+    fn conditional(&mut self, then: &Expr, then_ty: &Type, otherwise: &Expr, otherwise_ty: &Type, span: Span) -> Option<Type> {
+        match (then_ty, otherwise_ty) {
+            (then, otherwise) if then.is_arithmetic() && otherwise.is_arithmetic() => Some(common_type(then, otherwise)),
+            (then, otherwise) if then == otherwise => Some(then.clone()),
+            (Type::Pointer(pointee), Type::Pointer(_)) | (Type::Pointer(_), Type::Pointer(pointee)) if **pointee == Type::Void => {
+                Some(Type::Pointer(pointee.clone()))
+            },
+            (Type::Pointer(_), _) if is_null(otherwise) => Some(then_ty.clone()),
+            (_, Type::Pointer(_)) if is_null(then) => Some(otherwise_ty.clone()),
+            _ => self.error(format!("incompatible operand types ('{}' and '{}')", then_ty, otherwise_ty), span)
+        }
+    }
+
+    fn call(&mut self, callee: &mut Expr, args: &mut [Expr]) -> Option<Type> {
+        let callee_ty = self.rvalue(callee);
+        let function = match callee_ty {
+            Some(Type::Pointer(function)) if matches!(*function, Type::Function { .. }) => Some(*function),
+            Some(ty) => {
+                self.error(format!("called object type '{}' is not a function", ty), callee.span);
+                None
+            },
+            None => None
+        };
+
+        // Arguments are still checked when the callee isn't, so they all get types. Sema has
+        // already reported calls with the wrong number of arguments.
+        let params = match &function {
+            Some(Type::Function { params, .. }) => params.as_slice(),
+            _ => &[]
+        };
+        for (i, arg) in args.iter_mut().enumerate() {
+            let ty = self.value(arg);
+            if let (Some(param), Some(ty)) = (params.get(i), ty) {
+                self.convert(param, arg, &ty, "passing");
+            }
+        }
+
+        match function? {
+            Type::Function { return_type, .. } => Some(*return_type),
+            _ => None
+        }
+    }
+
+    fn member(&mut self, ty: &Type, name: &str, base: Span, span: Span) -> Option<Type> {
+        let fields = match ty {
+            Type::Struct(tag) => match self.structs.get(tag) {
+                Some(fields) => fields,
+                None => return self.error(format!("incomplete definition of type '{}'", ty), base)
+            },
+            ty => return self.error(format!("member reference base type '{}' is not a structure", ty), base)
+        };
+        match fields.iter().find(|field| field.name == name) {
+            Some(field) => Some(field.ty.clone()),
+            None => self.error(format!("no member named '{}' in '{}'", name, ty), span)
+        }
+    }
+
+    fn sizeof(&mut self, ty: &Type, span: Span) -> Option<Type> {
+        let invalid = match ty {
+            Type::Void | Type::Function { .. } => true,
+            Type::Struct(name) => !self.structs.contains_key(name),
+            _ => false
+        };
+        if invalid { return self.error(format!("invalid application of 'sizeof' to type '{}'", ty), span) }
+        Some(SIZE)
+    }
+}
@@ -0,0 +1,160 @@
+use std::fmt;
+
+use crate::{header::{Header, HEADER_SIZE, MAGIC}, isa::{self, Operand, REG_COUNT}, output};
+
+pub struct VerifyError {
+    // Word offset from the start of the code, or of the `Op.*` item in the text form
+    pub offset: usize,
+    pub message: String
+}
+
+fn error(offset: usize, message: String) -> VerifyError {
+    VerifyError { offset, message }
+}
+
+// Checks a bare code image: known opcodes, operands in range, no instruction running off the
+// end and every constant branch or call target on an instruction boundary
+pub fn verify_code(words: &[u32]) -> Vec<VerifyError> {
+    check_code(words).0
+}
+
+// Errors found in the code and which offsets start an instruction, None if decoding stopped
+// before the end of the code
+fn check_code(words: &[u32]) -> (Vec<VerifyError>, Option<Vec<bool>>) {
+    let mut errors = vec![];
+    let mut boundaries = vec![false; words.len()];
+    let mut targets = vec![];
+
+    let mut offset = 0;
+    while offset < words.len() {
+        let spec = match isa::op_by_opcode(words[offset]) {
+            Some(spec) => spec,
+            None => {
+                errors.push(error(offset, format!("unknown opcode {}", words[offset])));
+                return (errors, None);
+            }
+        };
+        boundaries[offset] = true;
+        if offset + spec.size() > words.len() {
+            errors.push(error(offset, format!("{} needs {} operands but the code ends after {}",
+                spec.name, spec.operands.len(), words.len() - offset - 1)));
+            return (errors, None);
+        }
+
+        for (index, operand) in spec.operands.iter().enumerate() {
+            let value = words[offset + 1 + index];
+            match operand {
+                Operand::Reg if value >= REG_COUNT => {
+                    errors.push(error(offset, format!("{} operand {}: register {} out of range", spec.name, index, value)));
+                },
+                Operand::Condition if isa::cond_by_value(value).is_none() => {
+                    errors.push(error(offset, format!("{} operand {}: unknown branch condition {}", spec.name, index, value)));
+                },
+                Operand::Addr => targets.push((offset, spec.name, value)),
+                _ => {}
+            }
+        }
+
+        offset += spec.size();
+    }
+
+    for (offset, name, target) in targets {
+        if target as usize >= words.len() {
+            errors.push(error(offset, format!("{} target {} is outside of the code", name, target)));
+        } else if !boundaries[target as usize] {
+            errors.push(error(offset, format!("{} target {} is in the middle of an instruction", name, target)));
+        }
+    }
+
+    (errors, Some(boundaries))
+}
+
+// Checks a full image: the header followed by the code and data section
+pub fn verify_image(words: &[u32]) -> Vec<VerifyError> {
+    let header = match Header::from_words(words) {
+        Ok(header) => header,
+        Err(message) => return vec![error(0, format!("header: {}", message))]
+    };
+
+    let code = &words[HEADER_SIZE..HEADER_SIZE + header.code_size as usize];
+    let (mut errors, boundaries) = check_code(code);
+    if let Some(boundaries) = boundaries {
+        if header.code_size > 0 && !boundaries[header.entry as usize] {
+            errors.push(error(header.entry as usize, "entry point is in the middle of an instruction".to_string()));
+        }
+    }
+
+    errors
+}
+
+// Converts the `Op.*` text form into words, checking that every opcode is followed by the
+// number of operands it takes
+pub fn parse_text(text: &str) -> Result<Vec<u32>, VerifyError> {
+    let items: Vec<&str> = text.split(',').map(|item| item.trim()).filter(|item| !item.is_empty()).collect();
+    let mut words = vec![];
+    let mut previous: Option<(usize, &isa::OpSpec)> = None;
+
+    let mut index = 0;
+    while index < items.len() {
+        let spec = match items[index].strip_prefix("Op.") {
+            Some(name) => match isa::op_by_name(name) {
+                Some(spec) => spec,
+                None => return Err(error(words.len(), format!("unknown opcode {}", items[index])))
+            },
+            None => return Err(match previous {
+                Some((start, spec)) => error(start, format!("{} expects {} operands, found extra value {}",
+                    spec.name, spec.operands.len(), items[index])),
+                None => error(0, format!("expected an opcode, found {}", items[index]))
+            })
+        };
+        let start = words.len();
+        previous = Some((start, spec));
+        words.push(spec.opcode);
+        index += 1;
+
+        for (count, operand) in spec.operands.iter().enumerate() {
+            let item = match items.get(index) {
+                Some(item) if !item.starts_with("Op.") => *item,
+                _ => return Err(error(start, format!("{} expects {} operands, found {}", spec.name, spec.operands.len(), count)))
+            };
+
+            let value = match (operand, item.strip_prefix("BranchType.")) {
+                (Operand::Condition, Some(name)) => isa::cond_by_name(name).map(|cond| cond.value),
+                (_, Some(_)) => None,
+                (_, None) => item.parse::<u32>().ok()
+            };
+            match value {
+                Some(value) => words.push(value),
+                None => return Err(error(start, format!("{} operand {}: invalid value {}", spec.name, count, item)))
+            }
+            index += 1;
+        }
+    }
+
+    Ok(words)
+}
+
+pub fn verify_text(text: &str) -> Vec<VerifyError> {
+    match parse_text(text) {
+        Ok(words) => verify_code(&words),
+        Err(err) => vec![err]
+    }
+}
+
+// Checks the output of the assembler in any format: the `Op.*` text form, or an image read
+// back with `output::read_words`, with or without a header
+pub fn verify_output(text: &str) -> Vec<VerifyError> {
+    if text.contains("Op.") { return verify_text(text) }
+
+    match output::read_words(text) {
+        Ok(words) if words.first() == Some(&MAGIC) => verify_image(&words),
+        Ok(words) => verify_code(&words),
+        Err(message) => vec![error(0, message)]
+    }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "error: offset {}: {}", self.offset, self.message)
+    }
+}
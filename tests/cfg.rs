@@ -0,0 +1,135 @@
+use compiler::{builder::{imm, r, sp, target, ProgramBuilder}, cfg::Cfg};
+
+// Every diagnostic for the program, as printed by the assembler
+fn check(program: ProgramBuilder) -> Vec<String> {
+    let program = program.build();
+    Cfg::build(&program).check().iter().map(|diagnostic| diagnostic.to_string()).collect()
+}
+
+#[test]
+fn accepts_a_loop_that_halts() {
+    let diagnostics = check(ProgramBuilder::new()
+        .label("main").mov(r(0), imm(0))
+        .label("loop").inc(r(0)).cmp(r(0), imm(5)).blt(target("loop"))
+        .label("done").cli().b(target("done")));
+    assert_eq!(diagnostics, Vec::<String>::new());
+}
+
+#[test]
+fn reports_code_after_an_unconditional_branch() {
+    let diagnostics = check(ProgramBuilder::new()
+        .label("main").b(target("main")).inc(r(0)).dec(r(0)));
+    assert_eq!(diagnostics, [".main+1: warning: unreachable code"]);
+}
+
+#[test]
+fn reports_a_label_only_reached_by_falling_through_dead_code() {
+    let diagnostics = check(ProgramBuilder::new()
+        .label("main").b(target("main"))
+        .label("dead").inc(r(0)).b(target("main")));
+    assert_eq!(diagnostics, [".dead+0: warning: unreachable code"]);
+}
+
+#[test]
+fn treats_call_targets_as_reachable() {
+    let diagnostics = check(ProgramBuilder::new()
+        .label("main").call(target("helper")).b(target("main"))
+        .label("helper").inc(r(0)).b(target("helper")));
+    assert_eq!(diagnostics, Vec::<String>::new());
+}
+
+#[test]
+fn starts_from_the_entry_label() {
+    let diagnostics = check(ProgramBuilder::new()
+        .label("unused").inc(r(0))
+        .label("main").b(target("main"))
+        .entry("main"));
+    assert_eq!(diagnostics, [".unused+0: warning: unreachable code"]);
+}
+
+#[test]
+fn treats_every_label_as_reachable_with_indirect_calls() {
+    let diagnostics = check(ProgramBuilder::new()
+        .label("main").call(r(1)).b(target("main"))
+        .label("anywhere").inc(r(0)).b(target("anywhere")));
+    assert_eq!(diagnostics, Vec::<String>::new());
+}
+
+#[test]
+fn reports_execution_falling_off_the_end() {
+    let diagnostics = check(ProgramBuilder::new().label("main").inc(r(0)));
+    assert_eq!(diagnostics, [".main+0: warning: execution falls off the end of the program"]);
+
+    let diagnostics = check(ProgramBuilder::new()
+        .label("main").cmp(r(0), imm(1)).beq(target("main")));
+    assert_eq!(diagnostics, [".main+1: warning: execution falls off the end of the program"]);
+}
+
+#[test]
+fn reports_undefined_targets() {
+    let diagnostics = check(ProgramBuilder::new()
+        .label("main").call(target("missing")).beq(target("nowhere")).b(target("main")));
+    assert_eq!(diagnostics, [
+        ".main+0: error: call to undefined label .missing",
+        ".main+1: error: branch to undefined label .nowhere"
+    ]);
+}
+
+#[test]
+fn reports_an_undefined_entry_label() {
+    let diagnostics = check(ProgramBuilder::new().label("main").b(target("main")).entry("start"));
+    assert_eq!(diagnostics[0], ".main+0: error: entry label .start is not defined");
+}
+
+#[test]
+fn reports_paths_joining_with_different_stack_depths() {
+    let diagnostics = check(ProgramBuilder::new()
+        .label("main").cmp(r(0), imm(0)).beq(target("join"))
+        .label("extra").push(r(1))
+        .label("join").b(target("join")));
+    assert_eq!(diagnostics, [".join+0: warning: stack imbalance: reached with 0 and 4 bytes pushed"]);
+}
+
+#[test]
+fn accepts_balanced_pushes_and_pops() {
+    let diagnostics = check(ProgramBuilder::new()
+        .label("main").cmp(r(0), imm(0)).beq(target("join"))
+        .label("extra").push(r(1)).add(sp(), sp(), imm(4))
+        .label("join").b(target("join")));
+    assert_eq!(diagnostics, Vec::<String>::new());
+}
+
+#[test]
+fn reports_popping_more_than_was_pushed() {
+    let diagnostics = check(ProgramBuilder::new()
+        .label("main").push(r(1)).add(sp(), sp(), imm(8)).b(target("main")));
+    assert_eq!(diagnostics, [".main+1: warning: stack pointer moved 4 bytes above the pushed values"]);
+}
+
+#[test]
+fn reports_values_left_on_the_stack_at_the_end() {
+    let diagnostics = check(ProgramBuilder::new().label("main").push(r(1)).cli());
+    assert_eq!(diagnostics, [
+        ".main+1: warning: execution falls off the end of the program",
+        ".main+1: warning: 4 bytes left on the stack at end of program"
+    ]);
+}
+
+#[test]
+fn writes_blocks_and_edges_as_dot() {
+    let program = ProgramBuilder::new()
+        .label("main").mov(r(0), imm(0))
+        .label("loop").inc(r(0)).cmp(r(0), imm(5)).blt(target("loop"))
+        .build();
+    let cfg = Cfg::build(&program);
+    assert_eq!(cfg.blocks.len(), 2);
+
+    let dot = cfg.to_dot();
+    assert!(dot.starts_with("digraph cfg {\n"));
+    assert!(dot.ends_with("}\n"));
+    assert!(dot.contains("\tb0 [label=\"main+0\\l"));
+    assert!(dot.contains("\tb1 [label=\"loop+0\\l"));
+    assert!(dot.contains("\tb0 -> b1;\n"));
+    assert!(dot.contains("\tb1 -> b1;\n"));
+    assert!(dot.contains("\tb1 -> end;\n"));
+}
@@ -0,0 +1,61 @@
+// Helpers shared by the tests of the C front end and the assembler
+#![allow(dead_code)]
+
+use compiler::{assembler::{Parser as _, Program}, ast::TranslationUnit, diagnostic::Diagnostic, lexer::Lexer, parser::Parser, sema::{self, SymbolTable}, typeck};
+
+pub fn messages(diagnostics: &[Diagnostic]) -> Vec<String> {
+    diagnostics.iter().map(|diagnostic| diagnostic.to_string()).collect()
+}
+
+// Tree and syntax errors of `source`, which must lex without errors
+pub fn parse_with_errors(source: &str) -> (TranslationUnit, Vec<String>) {
+    let mut lexer = Lexer::new();
+    lexer.set_program(source.to_string());
+    let (tokens, diagnostics) = lexer.lex();
+    assert!(diagnostics.is_empty(), "{:?}\n{}", diagnostics, source);
+    let mut parser = Parser::new();
+    parser.set_tokens(tokens);
+    let (unit, diagnostics) = parser.parse();
+
+    (unit, messages(&diagnostics))
+}
+
+pub fn parse(source: &str) -> TranslationUnit {
+    let (unit, errors) = parse_with_errors(source);
+    assert!(errors.is_empty(), "{:?}\n{}", errors, source);
+    unit
+}
+
+pub fn parse_errors(source: &str) -> Vec<String> {
+    parse_with_errors(source).1
+}
+
+// Tree, symbols and name errors of `source`, which must parse without errors
+pub fn analyze(source: &str) -> (TranslationUnit, SymbolTable, Vec<String>) {
+    let mut unit = parse(source);
+    let (symbols, diagnostics) = sema::analyze(&mut unit);
+    (unit, symbols, messages(&diagnostics))
+}
+
+// Typed tree and type errors of `source`, which must have no syntax or name errors
+pub fn check(source: &str) -> (TranslationUnit, Vec<String>) {
+    let (mut unit, symbols, errors) = analyze(source);
+    assert!(errors.is_empty(), "{:?}\n{}", errors, source);
+    let diagnostics = typeck::check(&mut unit, &symbols);
+    (unit, messages(&diagnostics))
+}
+
+// Program assembled from `source` and its diagnostics
+pub fn assemble_with_errors(source: &str) -> (Program, Vec<String>) {
+    let mut program = Program::new();
+    program.parse(source.to_string());
+    let errors = messages(&program.diagnostics);
+
+    (program, errors)
+}
+
+pub fn assemble(source: &str) -> Program {
+    let (program, errors) = assemble_with_errors(source);
+    assert!(errors.is_empty(), "{:?}\n{}", errors, source);
+    program
+}
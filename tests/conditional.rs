@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+
+mod common;
+
+use compiler::{assembler::{Arg, Parser as _, Program}, builder::{imm, r}, conditional};
+
+use common::assemble_with_errors;
+
+// Preprocesses `source` with the given command-line defines, returning the non-empty lines
+fn lines(source: &str, defines: &[(&str, &str)]) -> Vec<String> {
+    let mut defines: HashMap<String, String> = defines.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+    let (text, diagnostics) = conditional::preprocess(source, &mut defines);
+    assert!(diagnostics.is_empty(), "{:?}\n{}", diagnostics, source);
+    text.lines()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+fn errors(source: &str) -> Vec<String> {
+    conditional::preprocess(source, &mut HashMap::new()).1.iter().map(|diagnostic| diagnostic.to_string()).collect()
+}
+
+// Operands of every instruction after assembling `source`
+fn operands(source: &str, defines: &[(&str, &str)]) -> Vec<Vec<Arg>> {
+    let mut program = Program::new();
+    for (name, value) in defines {
+        program.define(name, value);
+    }
+    program.parse(source.to_string());
+    program.labels.iter().flat_map(|label| &label.instructions).map(|insn| insn.args.clone()).collect()
+}
+
+#[test]
+fn keeps_line_numbers() {
+    let source = ".if 0\nmov r0, 1\n.endif\ninc r0\n";
+    assert_eq!(conditional::preprocess(source, &mut HashMap::new()), ("\n\n\ninc r0\n".to_string(), vec![]));
+}
+
+#[test]
+fn takes_the_first_true_branch() {
+    let source = "
+        .if MODE == 1
+            one
+        .elif MODE == 2
+            two
+        .elif MODE == 2
+            again
+        .else
+            other
+        .endif";
+    assert_eq!(lines(source, &[("MODE", "1")]), ["one"]);
+    assert_eq!(lines(source, &[("MODE", "2")]), ["two"]);
+    assert_eq!(lines(source, &[("MODE", "3")]), ["other"]);
+    assert_eq!(lines(source, &[]), ["other"]);
+}
+
+#[test]
+fn evaluates_boolean_operators() {
+    let source = ".if (A || B) && !C\nyes\n.else\nno\n.endif";
+    assert_eq!(lines(source, &[("A", "1")]), ["yes"]);
+    assert_eq!(lines(source, &[("B", "1"), ("C", "1")]), ["no"]);
+    assert_eq!(lines(source, &[("A", "0")]), ["no"]);
+    assert_eq!(lines(".if HOST != node\nweb\n.endif", &[("HOST", "browser")]), ["web"]);
+    assert_eq!(lines(".if HOST == \"node\"\nnode\n.endif", &[("HOST", "node")]), ["node"]);
+}
+
+#[test]
+fn compares_numbers_in_any_radix() {
+    for value in ["16", "0x10", "0o20", "0b10000"] {
+        assert_eq!(lines(".if SIZE == 16\nyes\n.endif", &[("SIZE", value)]), ["yes"], "{}", value);
+    }
+}
+
+#[test]
+fn tests_whether_names_are_defined() {
+    let source = ".ifdef DEBUG\ndebug\n.endif\n.ifndef DEBUG\nrelease\n.endif\n.if defined(DEBUG) && DEBUG == 0\nzero\n.endif";
+    assert_eq!(lines(source, &[("DEBUG", "0")]), ["debug", "zero"]);
+    assert_eq!(lines(source, &[]), ["release"]);
+}
+
+#[test]
+fn nests_conditionals() {
+    let source = ".if A\n.if B\nab\n.else\na\n.endif\n.else\n.if B\nb\n.endif\nnone\n.endif";
+    assert_eq!(lines(source, &[("A", "1"), ("B", "1")]), ["ab"]);
+    assert_eq!(lines(source, &[("A", "1")]), ["a"]);
+    assert_eq!(lines(source, &[("B", "1")]), ["b", "none"]);
+}
+
+#[test]
+fn ignores_definitions_in_inactive_branches() {
+    let source = ".if 0\n.define X 1\n.else\n.define Y 1\n.endif\n.ifdef X\nx\n.endif\n.ifdef Y\ny\n.endif";
+    assert_eq!(lines(source, &[]), ["y"]);
+}
+
+#[test]
+fn replaces_operands_with_the_value_defined_at_that_line() {
+    let source = ".main:\n.define X 1\nmov r0, X\n.define X 2\nmov r1, X\n.undef X\n.define X 3\nadd r2, r2, X\n";
+    assert_eq!(operands(source, &[]), [
+        vec![r(0), imm(1)],
+        vec![r(1), imm(2)],
+        vec![r(2), r(2), imm(3)]
+    ]);
+}
+
+#[test]
+fn substitutes_uses_before_an_undef() {
+    assert_eq!(lines(".define COUNT 5\ncmp r0, COUNT\n.undef COUNT\n.ifdef COUNT\nstill\n.endif", &[]), ["cmp r0, 5"]);
+}
+
+#[test]
+fn leaves_mnemonics_and_label_references_alone() {
+    assert_eq!(lines(".define b 1\n.define loop 2\nb .loop\nmov r0, loop\n", &[]), ["b .loop", "mov r0, 2"]);
+}
+
+#[test]
+fn uses_command_line_defines() {
+    let source = ".main:\n.ifdef FAST\ninc r0\n.else\nadd r0, r0, STEP\n.endif\n";
+    assert_eq!(operands(source, &[("FAST", "1")]), [vec![r(0)]]);
+    assert_eq!(operands(source, &[("STEP", "0o17")]), [vec![r(0), r(0), imm(15)]]);
+}
+
+#[test]
+fn rejects_else_without_if() {
+    assert_eq!(errors(".else\n"), ["1:1: error: .else without .if"]);
+}
+
+#[test]
+fn rejects_elif_after_else() {
+    assert_eq!(errors(".if 1\n.else\n  .elif 1\n.endif\n"), ["3:3: error: .elif after .else"]);
+}
+
+#[test]
+fn rejects_unterminated_if() {
+    assert_eq!(errors("inc r0\n.if 1\n"), ["2:1: error: .if without .endif"]);
+}
+
+#[test]
+fn rejects_malformed_conditions_and_definitions() {
+    assert_eq!(errors(".if (A\n.endif\n"), ["1:1: error: unexpected end of condition"]);
+    assert_eq!(errors(".ifdef A B\n.endif\n"), ["1:1: error: .ifdef expects a single name"]);
+    assert_eq!(errors(".define\n"), ["1:1: error: .define expects a name"]);
+}
+
+#[test]
+fn reports_directive_errors_while_assembling() {
+    let (program, errors) = assemble_with_errors(".main:\n.if 1 +\n    inc r0\n.endif\n");
+    assert_eq!(errors, ["2:1: error: unexpected '+' in condition"]);
+    assert!(program.labels[0].instructions.is_empty());
+}
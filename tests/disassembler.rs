@@ -0,0 +1,39 @@
+mod common;
+
+use compiler::disassembler;
+
+use common::assemble;
+
+const SOURCE: &str = "
+.main:
+    mov r0, 0
+.loop:
+    inc r0
+    push r0
+    add sp, sp, 4
+    cmp r0, 5
+    blt .loop
+    calljs 1
+";
+
+#[test]
+fn disassembles_the_assembled_code() {
+    let listing = disassembler::disassemble(&assemble(SOURCE).encode().unwrap()).unwrap();
+    assert_eq!(listing, [
+        "    0: mov r0, 0",
+        "    3: inc r0",
+        "    5: push r0",
+        "    7: add sp, sp, 4",
+        "   11: cmp r0, 5",
+        "   14: blt 3",
+        "   17: calljs 1",
+        ""
+    ].join("\n"));
+}
+
+#[test]
+fn rejects_registers_out_of_range() {
+    assert_eq!(disassembler::disassemble(&[20, 127]), Ok("    0: inc flgs\n".to_string()));
+    assert_eq!(disassembler::disassemble(&[20, 128]), Err("register 128 out of range at offset 0".to_string()));
+    assert_eq!(disassembler::disassemble(&[20, 65537]), Err("register 65537 out of range at offset 0".to_string()));
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use compiler::{header, output::{self, EmitOptions, OutputFormat}};
+
+use common::assemble;
+
+const SOURCE: &str = ".main:\n    inc r0\n    b .main\n";
+
+#[test]
+fn skips_a_valid_header() {
+    let program = assemble(SOURCE);
+    let text = output::emit(&program, &EmitOptions { format: OutputFormat::Json, symbols: false, data: false }).unwrap();
+    let words = output::read_words(&text).unwrap();
+    assert_eq!(header::code_of(&words), Ok(&program.encode().unwrap()[..]));
+}
+
+#[test]
+fn treats_words_without_a_magic_number_as_bare_code() {
+    assert_eq!(header::code_of(&[20, 0, 22]), Ok(&[20, 0, 22][..]));
+}
+
+#[test]
+fn reports_header_errors() {
+    let program = assemble(SOURCE);
+    let mut words = program.header(false).unwrap().to_words();
+    words.extend(program.encode().unwrap());
+    words[1] = 2;
+    assert_eq!(header::code_of(&words), Err("unsupported format version 2 (expected 1)".to_string()));
+
+    words[1] = 1;
+    words.pop();
+    assert!(header::code_of(&words).unwrap_err().starts_with("image is"));
+}
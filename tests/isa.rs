@@ -0,0 +1,21 @@
+use compiler::{assembler::{Arg, Instruction}, isa};
+
+#[test]
+fn builds_every_instruction_in_the_table() {
+    for op in isa::OPS {
+        let args = op.operands.iter()
+            .filter(|operand| **operand != isa::Operand::Condition)
+            .map(|operand| match operand {
+                isa::Operand::Reg => Arg::Reg(1),
+                _ => Arg::Imm(2)
+            })
+            .collect();
+        let mnemonic = if op.is_conditional() { format!("{}{}", op.mnemonic, isa::CONDS[0].suffix) } else { op.mnemonic.to_string() };
+        let insn = Instruction::new(&mnemonic, args).unwrap();
+        assert_eq!(insn.spec().map(|spec| spec.name), Some(op.name));
+        assert_eq!(insn.source_mnemonic(), mnemonic);
+    }
+
+    assert_eq!(Instruction::new("mov", vec![]), None);
+    assert_eq!(Instruction::new("bxx", vec![Arg::Imm(0)]), None);
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use compiler::{assembler::Program, builder::target};
+
+use common::{assemble, assemble_with_errors};
+
+fn names(program: &Program) -> Vec<&str> {
+    program.labels.iter().map(|label| label.name.as_str()).collect()
+}
+
+fn errors(source: &str) -> Vec<String> {
+    assemble_with_errors(source).1
+}
+
+#[test]
+fn labels_with_a_single_dot_are_global_wherever_they_are() {
+    let program = assemble(".main:\n    inc r0\n.loop:\n    b .loop\n");
+    assert_eq!(names(&program), ["main", "loop"]);
+    assert_eq!(program.labels[1].instructions[0].args, [target("loop")]);
+
+    let program = assemble(".main:\n    inc r0\n    .loop:\n    b .loop\n.next: .other: cli\n");
+    assert_eq!(names(&program), ["main", "loop", "next", "other"]);
+}
+
+#[test]
+fn labels_with_two_dots_are_local_to_the_enclosing_global() {
+    let program = assemble(".main:\n    mov r0, 0\n..loop:\n    inc r0\n    b .loop\n");
+    assert_eq!(names(&program), ["main", "main.loop"]);
+    assert_eq!(program.labels[1].instructions[1].args, [target("main.loop")]);
+
+    let program = assemble(".main: ..loop: b .loop\n");
+    assert_eq!(names(&program), ["main", "main.loop"]);
+    assert_eq!(program.symbols(), [("main".to_string(), 0), ("main.loop".to_string(), 0)]);
+    assert_eq!(program.labels[1].instructions[0].args, [target("main.loop")]);
+}
+
+#[test]
+fn local_labels_do_not_collide_across_globals() {
+    let program = assemble("
+.first:
+  ..loop:
+    dec r0
+    b .loop
+.second:
+  ..loop:
+    inc r0
+    b .loop
+    b .first.loop
+");
+    assert_eq!(names(&program), ["first", "first.loop", "second", "second.loop"]);
+    assert_eq!(program.labels[1].instructions[1].args, [target("first.loop")]);
+    assert_eq!(program.labels[3].instructions[1].args, [target("second.loop")]);
+    assert_eq!(program.labels[3].instructions[2].args, [target("first.loop")]);
+}
+
+#[test]
+fn references_fall_back_to_global_labels() {
+    let program = assemble(".main:\n  ..loop:\n    b .done\n.done:\n    b .loop\n");
+    assert_eq!(program.labels[1].instructions[0].args, [target("done")]);
+    // `.loop` isn't defined in `.done`, so this is the (undefined) global `loop`
+    assert_eq!(program.labels[2].instructions[0].args, [target("loop")]);
+}
+
+#[test]
+fn resolves_numeric_labels_forwards_and_backwards() {
+    let program = assemble("
+.main:
+1:
+    inc r0
+    beq 1f
+    b 1b
+1:
+    dec r0
+    b 1b
+    b 2f
+2:
+    cli
+");
+    assert_eq!(names(&program), ["main", "1@0", "1@1", "2@0"]);
+    let args: Vec<_> = program.labels.iter().flat_map(|label| &label.instructions).map(|insn| insn.args.clone()).collect();
+    assert_eq!(args[1], [target("1@1")]);
+    assert_eq!(args[2], [target("1@0")]);
+    assert_eq!(args[4], [target("1@1")]);
+    assert_eq!(args[5], [target("2@0")]);
+
+    let symbols = program.symbol_table();
+    assert_eq!(symbols["1@0"], 0);
+    assert_eq!(symbols["1@1"], 7);
+}
+
+#[test]
+fn rejects_backward_references_before_the_label() {
+    assert_eq!(errors(".main:\n    b 1b\n1:\n    cli\n"), ["2:7: error: 1b refers to label 1 before its definition"]);
+}
+
+#[test]
+fn reports_duplicate_labels() {
+    assert_eq!(errors(".main:\n    inc r0\n.main:\n    cli\n"), ["3:1: error: duplicate label main"]);
+    assert_eq!(errors(".main:\n  ..loop:\n    inc r0\n  ..loop:\n    cli\n.other:\n  ..loop:\n    cli\n"), ["4:3: error: duplicate label main.loop"]);
+}
+
+#[test]
+fn rejects_labels_without_a_dot() {
+    assert_eq!(errors("main:\n    cli\n")[0], "1:1: error: expected a label or directive, found 'main:'");
+}
@@ -0,0 +1,111 @@
+use compiler::lexer::{Lexer, Token, TokenStream};
+
+// Characters the generated inputs are drawn from: everything the lexer treats specially,
+// plus some it doesn't know about and a few multi-byte ones
+const ALPHABET: &[char] = &[
+    'a', 'z', 'x', 'e', 'E', 'p', 'u', 'l', 'L', 'f', '_', '0', '1', '7', '8', '9',
+    ' ', '\t', '\n', '\r', '.', '+', '-', '*', '/', '%', '=', '!', '<', '>', '&', '|', '^', '~',
+    '?', ':', ',', ';', '(', ')', '[', ']', '{', '}', '"', '\'', '\\', '#', '@', '$', '`',
+    'é', 'λ', '€', '\0'
+];
+
+// xorshift64, so failures are reproducible from the seed
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+fn random_source(rng: &mut Rng) -> String {
+    let len = rng.below(48);
+    (0..len).map(|_| ALPHABET[rng.below(ALPHABET.len())]).collect()
+}
+
+// Lexes `source`, checking that every span lies within it, on character boundaries and in order
+fn check(source: &str) {
+    let mut lexer = Lexer::new();
+    lexer.set_program(source.to_string());
+    let (tokens, diagnostics) = lexer.lex();
+
+    let mut previous = 0;
+    for token in &tokens {
+        let end = token.span.offset + token.span.len;
+        assert!(end <= source.len(), "{:?}: span {:?} past the end", source, token);
+        assert!(source.is_char_boundary(token.span.offset) && source.is_char_boundary(end), "{:?}: span {:?} splits a character", source, token);
+        assert!(token.span.offset >= previous, "{:?}: token {:?} out of order", source, token);
+        previous = token.span.offset;
+    }
+    for diagnostic in &diagnostics {
+        let span = diagnostic.span().unwrap();
+        assert!(span.offset + span.len <= source.len(), "{:?}: diagnostic {} past the end", source, diagnostic);
+        lexer.source_map.snippet(span);
+    }
+}
+
+#[test]
+fn lex_never_panics_on_random_input() {
+    let mut rng = Rng(0x2545f4914f6cdd1d);
+    for _ in 0..20000 {
+        check(&random_source(&mut rng));
+    }
+}
+
+#[test]
+fn lex_never_panics_on_truncated_input() {
+    let source = include_str!("c.c");
+    for (end, _) in source.char_indices() {
+        check(&source[..end]);
+    }
+    for tail in ["=", "/", "/*", "/* *", "'", "'\\", "\"\\x", "0x", "1e", "1e+", ".", "...", "a", "'\\777'"] {
+        check(tail);
+        check(&format!("int x {}", tail));
+    }
+}
+
+#[test]
+fn lex_reports_every_invalid_character() {
+    let mut lexer = Lexer::new();
+    lexer.set_program("a@b $c\n`d` = 1;".to_string());
+    let (tokens, diagnostics) = lexer.lex();
+
+    let names: Vec<String> = tokens.iter().map(|token| format!("{:?}", token.token)).collect();
+    assert_eq!(names, ["Id(\"a\")", "Id(\"b\")", "Id(\"c\")", "Id(\"d\")", "Assign", "Num(1, IntSuffix { unsigned: false, long: false })", "Symbol(';')"]);
+    let locations: Vec<String> = diagnostics.iter().map(|diagnostic| diagnostic.location.to_string()).collect();
+    assert_eq!(locations, ["1:2", "1:5", "2:1", "2:3"]);
+}
+
+#[test]
+fn token_stream_peeks_without_consuming() {
+    let mut stream = TokenStream::new("x = \"a\" /* b */ \"c\";");
+    assert_eq!(stream.peek_nth(2).map(|item| item.as_ref().unwrap().token.clone()), Some(Token::Str("ac".to_string())));
+    assert_eq!(stream.peek().map(|item| item.as_ref().unwrap().token.clone()), Some(Token::Id("x".to_string())));
+
+    let tokens: Vec<Token> = stream.map(|item| item.unwrap().token).collect();
+    assert_eq!(tokens, [Token::Id("x".to_string()), Token::Assign(), Token::Str("ac".to_string()), Token::Symbol(';')]);
+}
+
+#[test]
+fn token_stream_keeps_warnings_out_of_the_items() {
+    let mut stream = TokenStream::new("a /* b /* c */ @");
+    let items: Vec<String> = stream.by_ref().map(|item| match item {
+        Ok(token) => format!("{:?}", token.token),
+        Err(diagnostic) => diagnostic.to_string()
+    }).collect();
+    assert_eq!(items, ["Id(\"a\")", "1:16: error: invalid character '@'"]);
+    let warnings: Vec<String> = stream.warnings().iter().map(|warning| warning.to_string()).collect();
+    assert_eq!(warnings, ["1:8: warning: '/*' within block comment"]);
+
+    let mut lexer = Lexer::new();
+    lexer.set_program("a /* b /* c */ @".to_string());
+    let diagnostics: Vec<String> = lexer.lex().1.iter().map(|diagnostic| diagnostic.to_string()).collect();
+    assert_eq!(diagnostics, ["1:8: warning: '/*' within block comment", "1:16: error: invalid character '@'"]);
+}
@@ -0,0 +1,198 @@
+mod common;
+
+use compiler::{assembler::Instruction, builder::{imm, ProgramBuilder}, header::{Header, HEADER_SIZE}, output::{self, EmitOptions, OutputFormat}};
+
+use common::{assemble, assemble_with_errors};
+
+const SOURCE: &str = "
+.entry .start
+.stack 0x1000
+.word 7, 8
+.main:
+    inc r0
+.start:
+    b .main
+";
+
+fn emit(format: OutputFormat, extras: bool) -> String {
+    output::emit(&assemble(SOURCE), &EmitOptions { format, symbols: extras, data: extras }).unwrap()
+}
+
+#[test]
+fn emits_a_fragment() {
+    assert_eq!(emit(OutputFormat::Fragment, false), "Op.INC, 0,\nOp.BRANCH_CONST, 0,\n");
+    assert_eq!(emit(OutputFormat::Fragment, true), emit(OutputFormat::Fragment, false));
+}
+
+#[test]
+fn emits_a_module() {
+    assert_eq!(emit(OutputFormat::Module, false), "\
+export const header = [1397574230, 1, 2, 4096, 4, 0];
+
+export const program = [
+    20, 0,
+    4, 0,
+];
+
+export const entry = 2;
+");
+    assert_eq!(emit(OutputFormat::Module, true), "\
+export const header = [1397574230, 1, 2, 4096, 4, 2];
+
+export const program = [
+    20, 0,
+    4, 0,
+];
+
+export const entry = 2;
+
+export const symbols = {
+    \"main\": 0,
+    \"start\": 2
+};
+
+export const data = [7, 8];
+");
+}
+
+#[test]
+fn emits_json() {
+    assert_eq!(emit(OutputFormat::Json, false), "[1397574230, 1, 2, 4096, 4, 0, 20, 0, 4, 0]\n");
+    assert_eq!(emit(OutputFormat::Json, true), "\
+{
+  \"header\": [1397574230, 1, 2, 4096, 4, 2],
+  \"program\": [20, 0, 4, 0],
+  \"entry\": 2,
+  \"symbols\": {
+    \"main\": 0,
+    \"start\": 2
+  },
+  \"data\": [7, 8]
+}
+");
+}
+
+#[test]
+fn emits_a_typed_array() {
+    assert_eq!(emit(OutputFormat::TypedArray, false), "\
+new Uint32Array([
+    /* header */ 1397574230, 1, 2, 4096, 4, 0,
+    20, 0,
+    4, 0,
+])
+");
+    assert_eq!(emit(OutputFormat::TypedArray, true), "\
+/* symbols:
+ * main = 0
+ * start = 2
+ */
+new Uint32Array([
+    /* header */ 1397574230, 1, 2, 4096, 4, 2,
+    20, 0,
+    4, 0,
+    /* data */ 7, 8,
+])
+");
+}
+
+#[test]
+fn starts_at_the_first_instruction_without_an_entry() {
+    let program = assemble(".main:\n    inc r0\n.next:\n    b .main\n");
+    assert_eq!(output::emit(&program, &EmitOptions { format: OutputFormat::Module, symbols: false, data: false }).unwrap().lines().last(),
+        Some("export const entry = 0;"));
+}
+
+#[test]
+fn accepts_data_after_the_code() {
+    let program = assemble(".main:\n    inc r0\n.word 1, 0x2\n");
+    assert_eq!(program.data, [1, 2]);
+    assert_eq!(program.labels[0].instructions.len(), 1);
+}
+
+#[test]
+fn reports_instructions_after_data_without_a_label() {
+    let (program, errors) = assemble_with_errors(".main:\n    inc r0\n.word 1\n    dec r0\n.next:\n    cli\n");
+    assert_eq!(errors, ["4:5: error: expected a label or directive, found 'dec'"]);
+    assert_eq!(program.labels.len(), 2);
+}
+
+#[test]
+fn reports_an_undefined_entry_label() {
+    let program = assemble(".entry .start\n.main:\n    cli\n");
+    for format in [OutputFormat::Module, OutputFormat::Json, OutputFormat::TypedArray] {
+        let result = output::emit(&program, &EmitOptions { format, symbols: false, data: false });
+        assert_eq!(result, Err("entry label .start is not defined".to_string()), "{:?}", format);
+    }
+}
+
+#[test]
+fn reads_back_every_numeric_format() {
+    let program = assemble(SOURCE);
+    let code = program.encode().unwrap();
+    for format in [OutputFormat::Module, OutputFormat::Json, OutputFormat::TypedArray] {
+        for symbols in [false, true] {
+            let text = output::emit(&program, &EmitOptions { format, symbols, data: false }).unwrap();
+            let words = output::read_words(&text).unwrap();
+            let header = Header::from_words(&words).unwrap();
+            assert_eq!(header.code_size as usize, code.len(), "{:?}", format);
+            assert_eq!(&words[HEADER_SIZE..], &code[..], "{:?}", format);
+        }
+    }
+}
+
+#[test]
+fn reads_back_the_data_section() {
+    let mut program = assemble(SOURCE);
+    program.data = vec![7, 8, 9];
+    for format in [OutputFormat::Module, OutputFormat::Json, OutputFormat::TypedArray] {
+        let text = output::emit(&program, &EmitOptions { format, symbols: true, data: true }).unwrap();
+        let words = output::read_words(&text).unwrap();
+        assert_eq!(Header::from_words(&words).unwrap().data_size, 3, "{:?}", format);
+        assert_eq!(&words[words.len() - 3..], &[7, 8, 9], "{:?}", format);
+    }
+}
+
+#[test]
+fn reads_bare_lists_of_words() {
+    assert_eq!(output::read_words("[1, 2,\n3]\n"), Ok(vec![1, 2, 3]));
+    assert_eq!(output::read_words("22 20 5"), Ok(vec![22, 20, 5]));
+}
+
+#[test]
+fn rejects_words_that_do_not_fit() {
+    assert_eq!(output::read_words("[1, 4294967296]"), Err("invalid word '4294967296'".to_string()));
+    assert_eq!(output::read_words("[1, -2]"), Err("invalid word '-2'".to_string()));
+}
+
+#[test]
+fn reports_an_undefined_branch_target() {
+    let program = assemble(".main:\n    b .nowhere\n");
+    for format in [OutputFormat::Fragment, OutputFormat::Module, OutputFormat::Json, OutputFormat::TypedArray] {
+        let result = output::emit(&program, &EmitOptions { format, symbols: false, data: false });
+        assert_eq!(result, Err("undefined label .nowhere".to_string()), "{:?}", format);
+    }
+}
+
+#[test]
+fn reports_operands_without_an_encoding() {
+    let program = ProgramBuilder::new().label("main").insn(Instruction { mnemonic: "mov", cond: None, args: vec![imm(1), imm(2)] }).build();
+    assert_eq!(program.encode(), Err("no encoding for Mov(#1, #2)".to_string()));
+}
+
+#[test]
+fn reports_directives_with_bad_arguments() {
+    let (program, errors) = assemble_with_errors(".stack r1\n.main:\n    inc r0\n");
+    assert_eq!(errors, ["1:8: error: .stack expects an immediate value, found 'r1'"]);
+    assert_eq!(program.labels.len(), 1);
+
+    let (program, errors) = assemble_with_errors(".main:\n    inc r0\n.word 1, .main,  x\n");
+    assert_eq!(errors, ["3:10: error: .word expects immediate values, found '.main'", "3:18: error: .word expects immediate values, found 'x'"]);
+    assert_eq!(program.data, [1]);
+}
+
+#[test]
+fn reports_unknown_directives() {
+    let (program, errors) = assemble_with_errors(".bogus 1\n.main:\n    inc r0\n");
+    assert_eq!(errors, ["1:1: error: unknown directive .bogus"]);
+    assert_eq!(program.labels.len(), 1);
+}
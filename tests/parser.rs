@@ -0,0 +1,318 @@
+mod common;
+
+use compiler::{ast::{BinaryOp, Expr, ExprKind, ExternalDecl, ForInit, FunctionDecl, Initializer, IntKind, Stmt, StmtKind, Storage, Type, UnaryOp, VarDecl}, lexer::Lexer, parser::Parser};
+
+use common::{parse, parse_errors, parse_with_errors};
+
+fn int() -> Type {
+    Type::Int { kind: IntKind::Int, unsigned: false }
+}
+
+fn pointer(ty: Type) -> Type {
+    Type::Pointer(Box::new(ty))
+}
+
+fn array(ty: Type, length: usize) -> Type {
+    Type::Array(Box::new(ty), Some(length))
+}
+
+fn variables(source: &str) -> Vec<VarDecl> {
+    parse(source).items.into_iter().filter_map(|item| match item {
+        ExternalDecl::Variable(decl) => Some(decl),
+        _ => None
+    }).collect()
+}
+
+fn types(source: &str) -> Vec<Type> {
+    variables(source).into_iter().map(|decl| decl.ty).collect()
+}
+
+// Signatures of the prototypes and function definitions in `source`
+fn functions(source: &str) -> Vec<FunctionDecl> {
+    parse(source).items.into_iter().filter_map(|item| match item {
+        ExternalDecl::Prototype(decl) => Some(decl),
+        ExternalDecl::Function(function) => Some(function.decl),
+        _ => None
+    }).collect()
+}
+
+// Statements of a function body holding `body`
+fn statements(body: &str) -> Vec<Stmt> {
+    let unit = parse(&format!("void f(void) {{\n{}\n}}\n", body));
+    let ExternalDecl::Function(function) = &unit.items[0] else { panic!("{:?}", unit.items[0]) };
+    function.body.stmts.clone()
+}
+
+// `source` parsed as an expression, with every operation in parentheses
+fn grouped(source: &str) -> String {
+    let mut lexer = Lexer::new();
+    lexer.set_program(source.to_string());
+    let (tokens, _) = lexer.lex();
+    let count = tokens.len();
+    let mut parser = Parser::new();
+    parser.set_tokens(tokens);
+    let expr = parser.expression().unwrap();
+    assert_eq!(parser.tok, count, "{} not fully parsed", source);
+
+    render(&expr)
+}
+
+fn render(expr: &Expr) -> String {
+    match &expr.kind {
+        ExprKind::Ident(name, _) => name.clone(),
+        ExprKind::Int(value, _) => value.to_string(),
+        ExprKind::Unary(op @ (UnaryOp::PostInc | UnaryOp::PostDec), operand) => format!("({}{})", render(operand), op.as_str()),
+        ExprKind::Unary(op, operand) => format!("({}{})", op.as_str(), render(operand)),
+        ExprKind::Binary(op, left, right) => format!("({} {} {})", render(left), op.as_str(), render(right)),
+        ExprKind::Assign(op, target, value) => format!("({} {}= {})", render(target), op.map_or("", |op| op.as_str()), render(value)),
+        ExprKind::Conditional(condition, then, otherwise) => format!("({} ? {} : {})", render(condition), render(then), render(otherwise)),
+        ExprKind::Call(function, args) => format!("{}({})", render(function), args.iter().map(render).collect::<Vec<_>>().join(", ")),
+        ExprKind::Index(array, index) => format!("{}[{}]", render(array), render(index)),
+        ExprKind::Member(base, field) => format!("{}.{}", render(base), field),
+        ExprKind::Arrow(base, field) => format!("{}->{}", render(base), field),
+        ExprKind::SizeofExpr(operand) => format!("(sizeof {})", render(operand)),
+        ExprKind::SizeofType(ty) => format!("sizeof({})", ty),
+        ExprKind::Cast(ty, operand) => format!("(({}){})", ty, render(operand)),
+        kind => format!("{:?}", kind)
+    }
+}
+
+#[test]
+fn carries_on_after_a_missing_semicolon_at_the_end_of_a_line() {
+    let (unit, errors) = parse_with_errors("int main(void) {\n    int a = 1\n    a = 2;\n    return a\n}\n");
+    assert_eq!(errors, ["3:5: error: expected ';', found 'a'", "5:1: error: expected ';', found '}'"]);
+    let ExternalDecl::Function(function) = &unit.items[0] else { panic!("{:?}", unit.items[0]) };
+    assert_eq!(function.body.stmts.len(), 3);
+}
+
+#[test]
+fn resumes_at_the_next_statement() {
+    let (unit, errors) = parse_with_errors("int main(void) {\n    a = a + ;\n    if (a) b; else ;\n    x = (1 2) while (x) x--;\n    return a;\n}\n");
+    assert_eq!(errors, ["2:13: error: expected an expression, found ';'", "4:12: error: expected ')', found '2'"]);
+    let ExternalDecl::Function(function) = &unit.items[0] else { panic!("{:?}", unit.items[0]) };
+    let kinds: Vec<_> = function.body.stmts.iter().map(|stmt| match stmt.kind {
+        StmtKind::If(..) => "if",
+        StmtKind::While(..) => "while",
+        StmtKind::Return(_) => "return",
+        _ => "other"
+    }).collect();
+    assert_eq!(kinds, ["if", "while", "return"]);
+}
+
+#[test]
+fn resumes_at_the_next_declaration() {
+    let (unit, errors) = parse_with_errors("int g = ;\nint f(int x { return x; }\nint ok(void) { return 1; }\n");
+    assert_eq!(errors, ["1:9: error: expected an expression, found ';'", "2:13: error: expected ')', found '{'"]);
+    assert!(matches!(unit.items.as_slice(), [ExternalDecl::Function(function)] if function.decl.name == "ok"));
+}
+
+#[test]
+fn parses_pointer_and_array_declarators() {
+    assert_eq!(types("int *p, a[3], **q[2][4], b[];"), [
+        pointer(int()),
+        array(int(), 3),
+        array(array(pointer(pointer(int())), 4), 2),
+        Type::Array(Box::new(int()), None)
+    ]);
+    // Qualifiers after a `*` apply to the pointer and are dropped
+    assert_eq!(types("char *const volatile s;"), [pointer(Type::Int { kind: IntKind::Char, unsigned: false })]);
+}
+
+#[test]
+fn combines_type_specifiers() {
+    let int = |kind, unsigned| Type::Int { kind, unsigned };
+    assert_eq!(types("unsigned u; short int s; long long l; unsigned char c; signed sc; long unsigned int lu; long double d;"), [
+        int(IntKind::Int, true),
+        int(IntKind::Short, false),
+        int(IntKind::Long, false),
+        int(IntKind::Char, true),
+        int(IntKind::Int, false),
+        int(IntKind::Long, true),
+        Type::Double
+    ]);
+
+    assert_eq!(parse_errors("long char c;"), ["1:1: error: invalid combination of type specifiers"]);
+    assert_eq!(parse_errors("int float x;"), ["1:5: error: two or more data types in declaration"]);
+    assert_eq!(parse_errors("unsigned signed x;"), ["1:10: error: duplicate 'signed'"]);
+    assert_eq!(parse_errors("static extern int x;"), ["1:8: error: multiple storage classes in declaration"]);
+}
+
+#[test]
+fn keeps_storage_classes_and_const() {
+    let decls = variables("static int a; extern const int b; const char *c;");
+    let flags: Vec<_> = decls.iter().map(|decl| (decl.name.as_str(), decl.storage, decl.is_const)).collect();
+    assert_eq!(flags, [("a", Some(Storage::Static), false), ("b", Some(Storage::Extern), true), ("c", None, true)]);
+}
+
+#[test]
+fn parses_prototypes_and_definitions() {
+    let decls = functions("int f(void);\nlong g(char *s, int, ...);\nstatic int *h(int a[]) { return a; }\nint x, k(int);\n");
+    let signatures: Vec<_> = decls.iter().map(|decl| {
+        let params: Vec<_> = decl.params.iter().map(|param| (param.ty.clone(), param.name.clone())).collect();
+        (decl.name.as_str(), decl.return_type.clone(), params, decl.variadic, decl.storage)
+    }).collect();
+
+    assert_eq!(signatures, [
+        ("f", int(), vec![], false, None),
+        ("g", Type::Int { kind: IntKind::Long, unsigned: false }, vec![
+            (pointer(Type::Int { kind: IntKind::Char, unsigned: false }), Some("s".to_string())),
+            (int(), None)
+        ], true, None),
+        ("h", pointer(int()), vec![(Type::Array(Box::new(int()), None), Some("a".to_string()))], false, Some(Storage::Static)),
+        ("k", int(), vec![(int(), None)], false, None)
+    ]);
+}
+
+#[test]
+fn tells_empty_parameter_lists_from_void() {
+    let prototyped: Vec<_> = functions("int f();\nint g(void);\nint h(int, ...);\nint k() { return 0; }\n").iter().map(|decl| decl.prototyped).collect();
+    assert_eq!(prototyped, [false, true, true, false]);
+}
+
+#[test]
+fn rejects_malformed_function_declarators() {
+    assert_eq!(parse_errors("int a, f(void) { return 1; }\n"), ["1:16: error: expected ';', found '{'"]);
+    assert_eq!(parse_errors("int f(static int a);\n"), ["1:7: error: invalid parameter declaration"]);
+    assert_eq!(parse_errors("int f(int a, ...,);\n"), ["1:17: error: expected ')', found ','"]);
+}
+
+#[test]
+fn parses_struct_definitions() {
+    let source = "struct point { int x, y; char *name; } origin, *p;\nstruct point q;\nstruct empty;\n";
+    let unit = parse(source);
+    let ExternalDecl::Struct(decl) = &unit.items[0] else { panic!("{:?}", unit.items[0]) };
+    let fields: Vec<_> = decl.fields.iter().map(|field| (field.name.as_str(), field.ty.clone())).collect();
+    assert_eq!(decl.name, "point");
+    assert_eq!(fields, [("x", int()), ("y", int()), ("name", pointer(Type::Int { kind: IntKind::Char, unsigned: false }))]);
+
+    let point = Type::Struct("point".to_string());
+    assert_eq!(unit.items.len(), 4);
+    assert_eq!(types(source), [point.clone(), pointer(point.clone()), point]);
+}
+
+#[test]
+fn rejects_structs_outside_file_scope() {
+    assert_eq!(parse_errors("struct s { static int x; };\n")[0], "1:12: error: nested struct definitions and storage classes are not supported in fields");
+    assert_eq!(parse_errors("int main(void) {\n    struct s { int x; } v;\n}\n"), ["2:5: error: struct definitions are only supported at file scope"]);
+}
+
+#[test]
+fn parses_nested_initializer_lists() {
+    let decls = variables("int a = 1 + 2;\nint b[2][2] = {{1, 2}, {3}, };\nint c[1] = {};\n");
+    assert!(matches!(decls[0].init, Some(Initializer::Expr(_))));
+
+    let Some(Initializer::List(rows, _)) = &decls[1].init else { panic!("{:?}", decls[1].init) };
+    let lengths: Vec<_> = rows.iter().map(|row| match row {
+        Initializer::List(items, _) => items.len(),
+        Initializer::Expr(_) => 0
+    }).collect();
+    assert_eq!(lengths, [2, 1]);
+    assert_eq!(rows[1].span().to_string(), "2:24");
+
+    assert!(matches!(&decls[2].init, Some(Initializer::List(items, _)) if items.is_empty()));
+    assert_eq!(parse_errors("int a[2] = {1 2};\n")[0], "1:15: error: expected '}', found '2'");
+}
+
+#[test]
+fn binds_binary_operators_by_precedence() {
+    assert_eq!(grouped("a + b * c - d / e % f"), "((a + (b * c)) - ((d / e) % f))");
+    assert_eq!(grouped("a || b && c | d ^ e & f == g < h << i + j * k"), "(a || (b && (c | (d ^ (e & (f == (g < (h << (i + (j * k))))))))))");
+    assert_eq!(grouped("a * b + c << d > e != f & g ^ h | i && j || k"), "((((((((((a * b) + c) << d) > e) != f) & g) ^ h) | i) && j) || k)");
+    assert_eq!(grouped("(a + b) * c"), "((a + b) * c)");
+}
+
+#[test]
+fn binary_operators_are_left_associative() {
+    assert_eq!(grouped("a - b - c"), "((a - b) - c)");
+    assert_eq!(grouped("a / b * c % d"), "(((a / b) * c) % d)");
+    assert_eq!(grouped("a << b >> c"), "((a << b) >> c)");
+    assert_eq!(grouped("a < b == c < d != e"), "(((a < b) == (c < d)) != e)");
+    assert_eq!(grouped("a && b && c || d || e"), "((((a && b) && c) || d) || e)");
+}
+
+#[test]
+fn assignments_and_conditionals_are_right_associative() {
+    assert_eq!(grouped("a = b += c <<= 1"), "(a = (b += (c <<= 1)))");
+    assert_eq!(grouped("a ? b : c ? d : e"), "(a ? b : (c ? d : e))");
+    assert_eq!(grouped("a ? b ? c : d : e"), "(a ? (b ? c : d) : e)");
+    assert_eq!(grouped("a = b || c ? d : e"), "(a = ((b || c) ? d : e))");
+    // The middle operand is a full expression
+    assert_eq!(grouped("a ? b, c : d"), "(a ? (b , c) : d)");
+}
+
+#[test]
+fn the_comma_operator_binds_loosest() {
+    assert_eq!(grouped("a = 1, b = 2, c"), "(((a = 1) , (b = 2)) , c)");
+    assert_eq!(grouped("f(a, (b, c))"), "f(a, (b , c))");
+}
+
+#[test]
+fn binds_unary_and_postfix_operators_tighter_than_binary_ones() {
+    assert_eq!(grouped("-a++ * *p--"), "((-(a++)) * (*(p--)))");
+    assert_eq!(grouped("!a == ~b"), "((!a) == (~b))");
+    assert_eq!(grouped("- -a"), "(-(-a))");
+    assert_eq!(grouped("*p->next[1].x"), "(*p->next[1].x)");
+    assert_eq!(grouped("&a[0] + ++i"), "((&a[0]) + (++i))");
+    assert_eq!(grouped("f(a, b = 1)(c)"), "f(a, (b = 1))(c)");
+    assert_eq!(grouped("sizeof a + 1"), "((sizeof a) + 1)");
+    assert_eq!(grouped("sizeof(int) * 2"), "(sizeof(int) * 2)");
+    assert_eq!(grouped("(unsigned char *)p + 1"), "(((unsigned char *)p) + 1)");
+}
+
+#[test]
+fn reports_incomplete_expressions() {
+    assert_eq!(parse_errors("int x = (1 + 2;\n"), ["1:15: error: expected ')', found ';'"]);
+    assert_eq!(parse_errors("int x = 1 ? 2;\n"), ["1:14: error: expected ':', found ';'"]);
+    assert_eq!(parse_errors("int x = a[1;\n"), ["1:12: error: expected ']', found ';'"]);
+}
+
+#[test]
+fn binds_else_to_the_nearest_if() {
+    let stmts = statements("if (a) if (b) x; else y;");
+    let StmtKind::If(_, then, None) = &stmts[0].kind else { panic!("{:?}", stmts[0]) };
+    assert!(matches!(&then.kind, StmtKind::If(_, _, Some(_))));
+
+    let stmts = statements("if (a) { if (b) x; } else y;");
+    let StmtKind::If(_, then, Some(_)) = &stmts[0].kind else { panic!("{:?}", stmts[0]) };
+    assert!(matches!(&then.kind, StmtKind::Block(block) if matches!(block.stmts[0].kind, StmtKind::If(_, _, None))));
+
+    let stmts = statements("if (a) x; else if (b) y; else z;");
+    let StmtKind::If(_, _, Some(otherwise)) = &stmts[0].kind else { panic!("{:?}", stmts[0]) };
+    assert!(matches!(&otherwise.kind, StmtKind::If(_, _, Some(_))));
+}
+
+#[test]
+fn parses_declarations_in_for_loops() {
+    let stmts = statements("for (int i = 0, *p; i < n; i++) ;");
+    let StmtKind::For { init: Some(ForInit::Decl(decls)), condition: Some(condition), step: Some(step), body } = &stmts[0].kind else { panic!("{:?}", stmts[0]) };
+    let names: Vec<_> = decls.iter().map(|decl| (decl.name.as_str(), decl.ty.clone(), decl.init.is_some())).collect();
+    assert_eq!(names, [("i", int(), true), ("p", pointer(int()), false)]);
+    assert!(matches!(condition.kind, ExprKind::Binary(BinaryOp::Lt, _, _)));
+    assert!(matches!(step.kind, ExprKind::Unary(UnaryOp::PostInc, _)));
+    assert_eq!(body.kind, StmtKind::Empty);
+}
+
+#[test]
+fn leaves_out_empty_for_clauses() {
+    let stmts = statements("for (;;) break;\nfor (i = 0; ; ) continue;");
+    assert!(matches!(&stmts[0].kind, StmtKind::For { init: None, condition: None, step: None, body } if body.kind == StmtKind::Break));
+    assert!(matches!(&stmts[1].kind, StmtKind::For { init: Some(ForInit::Expr(_)), condition: None, step: None, body } if body.kind == StmtKind::Continue));
+
+    assert_eq!(parse_errors("void f(void) {\n    for (int i = 0 i < 2; i++);\n}\n")[0], "2:20: error: expected ';', found 'i'");
+    assert_eq!(parse_errors("void f(void) {\n    for (i = 0, i < 2) ;\n}\n")[0], "2:22: error: expected ';', found ')'");
+}
+
+#[test]
+fn parses_do_while_loops() {
+    let stmts = statements("do x--; while (x > 0);\ndo { x++; } while (x);");
+    let StmtKind::DoWhile(body, condition) = &stmts[0].kind else { panic!("{:?}", stmts[0]) };
+    assert!(matches!(&body.kind, StmtKind::Expr(expr) if matches!(expr.kind, ExprKind::Unary(UnaryOp::PostDec, _))));
+    assert!(matches!(condition.kind, ExprKind::Binary(BinaryOp::Gt, _, _)));
+    assert_eq!(stmts[0].span.to_string(), "2:1");
+    assert!(matches!(&stmts[1].kind, StmtKind::DoWhile(body, _) if matches!(body.kind, StmtKind::Block(_))));
+
+    // The `;` after the condition may be missing at the end of a line
+    assert_eq!(parse_errors("void f(void) {\n    do x--; while (x)\n}\n"), ["3:1: error: expected ';', found '}'"]);
+    assert_eq!(parse_errors("void f(void) {\n    do x--; (x);\n}\n")[0], "2:13: error: expected 'while', found '('");
+    assert_eq!(parse_errors("void f(void) {\n    do x--; while x;\n}\n")[0], "2:19: error: expected '(', found 'x'");
+}
@@ -0,0 +1,131 @@
+use compiler::{assembler::Program, builder::{imm, r, sp, target, ProgramBuilder}, peephole};
+
+// Optimizes `before` and checks it becomes `after`, with the savings reported
+fn assert_optimized(before: ProgramBuilder, after: ProgramBuilder) {
+    let mut program = before.build();
+    let expected = after.build();
+    let size = program.code_size();
+
+    let saved = peephole::optimize(&mut program);
+    assert_eq!(program.labels, expected.labels);
+    assert_eq!(saved, size - expected.code_size());
+    assert!(saved > 0);
+}
+
+fn assert_unchanged(program: ProgramBuilder) {
+    let mut program: Program = program.build();
+    let expected = program.clone();
+    assert_eq!(peephole::optimize(&mut program), 0);
+    assert_eq!(program.labels, expected.labels);
+}
+
+#[test]
+fn removes_moves_to_the_same_register() {
+    assert_optimized(
+        ProgramBuilder::new().label("main").mov(r(1), r(1)).inc(r(1)),
+        ProgramBuilder::new().label("main").inc(r(1))
+    );
+}
+
+#[test]
+fn keeps_moves_between_registers() {
+    assert_unchanged(ProgramBuilder::new().label("main").mov(r(1), r(2)).mov(r(1), imm(1)));
+}
+
+#[test]
+fn removes_adding_zero_in_place() {
+    assert_optimized(
+        ProgramBuilder::new().label("main").add(r(3), r(3), imm(0)).cli(),
+        ProgramBuilder::new().label("main").cli()
+    );
+}
+
+#[test]
+fn keeps_adding_zero_into_another_register() {
+    assert_unchanged(ProgramBuilder::new().label("main").add(r(3), r(4), imm(0)).add(r(3), r(3), r(0)));
+}
+
+#[test]
+fn merges_consecutive_immediate_adds() {
+    assert_optimized(
+        ProgramBuilder::new().label("main").add(r(2), r(2), imm(3)).add(r(2), r(2), imm(4)).add(r(2), r(2), imm(5)),
+        ProgramBuilder::new().label("main").add(r(2), r(2), imm(12))
+    );
+}
+
+#[test]
+fn keeps_adds_to_different_registers_or_across_labels() {
+    assert_unchanged(ProgramBuilder::new().label("main").add(r(2), r(2), imm(3)).add(r(3), r(3), imm(4)));
+    assert_unchanged(ProgramBuilder::new().label("main").add(r(2), r(1), imm(3)).add(r(2), r(2), imm(4)));
+    assert_unchanged(ProgramBuilder::new().label("main").add(r(2), r(2), imm(3)).label("next").add(r(2), r(2), imm(4)));
+}
+
+#[test]
+fn keeps_adds_whose_sum_overflows() {
+    assert_unchanged(ProgramBuilder::new().label("main").add(r(2), r(2), imm(u32::MAX)).add(r(2), r(2), imm(1)));
+}
+
+#[test]
+fn cancels_a_push_popped_straight_away() {
+    assert_optimized(
+        ProgramBuilder::new().label("main").push(r(1)).add(sp(), sp(), imm(4)).cli(),
+        ProgramBuilder::new().label("main").cli()
+    );
+}
+
+#[test]
+fn shrinks_a_larger_stack_adjustment_after_a_push() {
+    assert_optimized(
+        ProgramBuilder::new().label("main").push(r(1)).add(sp(), sp(), imm(12)),
+        ProgramBuilder::new().label("main").add(sp(), sp(), imm(8))
+    );
+}
+
+#[test]
+fn keeps_a_push_followed_by_a_smaller_adjustment() {
+    assert_unchanged(ProgramBuilder::new().label("main").push(r(1)).add(sp(), sp(), imm(2)));
+    assert_unchanged(ProgramBuilder::new().label("main").push(r(1)).add(r(1), r(1), imm(4)));
+}
+
+#[test]
+fn removes_a_branch_to_the_next_label() {
+    assert_optimized(
+        ProgramBuilder::new().label("main").inc(r(0)).b(target("next")).label("next").cli(),
+        ProgramBuilder::new().label("main").inc(r(0)).label("next").cli()
+    );
+    assert_optimized(
+        ProgramBuilder::new().label("main").cmp(r(0), imm(1)).beq(target("next")).label("next").cli(),
+        ProgramBuilder::new().label("main").cmp(r(0), imm(1)).label("next").cli()
+    );
+}
+
+#[test]
+fn removes_a_branch_across_empty_labels() {
+    assert_optimized(
+        ProgramBuilder::new().label("main").b(target("last")).label("empty").label("last").cli(),
+        ProgramBuilder::new().label("main").label("empty").label("last").cli()
+    );
+}
+
+#[test]
+fn keeps_a_branch_past_a_non_empty_label() {
+    assert_unchanged(ProgramBuilder::new().label("main").b(target("last")).label("middle").inc(r(0)).label("last").cli());
+    assert_unchanged(ProgramBuilder::new().label("main").b(target("main")).label("next").cli());
+}
+
+#[test]
+fn keeps_a_branch_that_is_not_last_in_its_label() {
+    assert_unchanged(ProgramBuilder::new().label("main").b(target("next")).inc(r(0)).label("next").cli());
+}
+
+#[test]
+fn rewrites_until_nothing_changes() {
+    // Removing the no-op exposes the pair of adds, and emptying `main` the branch over it
+    assert_optimized(
+        ProgramBuilder::new()
+            .label("start").add(r(1), r(1), imm(1)).mov(r(2), r(2)).add(r(1), r(1), imm(2)).b(target("end"))
+            .label("main").push(r(0)).add(sp(), sp(), imm(4))
+            .label("end").cli(),
+        ProgramBuilder::new().label("start").add(r(1), r(1), imm(3)).label("main").label("end").cli()
+    );
+}
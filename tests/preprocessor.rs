@@ -0,0 +1,190 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use compiler::preprocessor::Preprocessor;
+
+// Fresh directory for the files of one test
+fn directory(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("preprocessor-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write(dir: &Path, name: &str, source: &str) -> String {
+    let path = dir.join(name);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, source).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+// Preprocessed text with blank lines removed and each line trimmed
+fn preprocess(source: &str) -> Result<Vec<String>, String> {
+    lines(&mut Preprocessor::new(), source, "main.c")
+}
+
+fn lines(preprocessor: &mut Preprocessor, source: &str, file: &str) -> Result<Vec<String>, String> {
+    match preprocessor.preprocess(source, file) {
+        Ok(preprocessed) => Ok(preprocessed.text.lines()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect()),
+        Err(err) => Err(err.to_string())
+    }
+}
+
+#[test]
+fn includes_quoted_files_relative_to_the_including_file() {
+    let dir = directory("quoted");
+    write(&dir, "lib/util.h", "int util;\n#include \"inner.h\"\n");
+    write(&dir, "lib/inner.h", "int inner;\n");
+    let main = write(&dir, "main.c", "#include \"lib/util.h\"\nint main;\n");
+
+    let mut preprocessor = Preprocessor::new();
+    let preprocessed = preprocessor.preprocess_file(&main).unwrap();
+    assert_eq!(preprocessed.text, "int util;\nint inner;\n\n\nint main;\n");
+}
+
+#[test]
+fn searches_include_paths() {
+    let dir = directory("paths");
+    write(&dir, "sys/stdio.h", "int printf;\n");
+    write(&dir, "local/config.h", "int config;\n");
+
+    let mut preprocessor = Preprocessor::new();
+    preprocessor.add_include_path(&dir.join("sys").to_string_lossy());
+    preprocessor.add_include_path(&dir.join("local").to_string_lossy());
+    let file = dir.join("main.c").to_string_lossy().into_owned();
+    assert_eq!(lines(&mut preprocessor, "#include <stdio.h>\n#include \"config.h\"\n", &file),
+        Ok(vec!["int printf;".to_string(), "int config;".to_string()]));
+}
+
+#[test]
+fn does_not_search_the_current_directory_for_angle_includes() {
+    let dir = directory("angle");
+    write(&dir, "local.h", "int local;\n");
+    let main = write(&dir, "main.c", "#include <local.h>\n");
+
+    let err = Preprocessor::new().preprocess_file(&main).unwrap_err();
+    assert_eq!(err.message, "cannot find include file local.h");
+    assert_eq!(err.line, 1);
+}
+
+#[test]
+fn limits_include_depth() {
+    let dir = directory("depth");
+    let main = write(&dir, "self.h", "#include \"self.h\"\n");
+
+    let err = Preprocessor::new().preprocess_file(&main).unwrap_err();
+    assert_eq!(err.message, "#include nested more than 64 levels deep");
+}
+
+#[test]
+fn maps_lines_back_to_their_files() {
+    let dir = directory("lines");
+    let header = write(&dir, "defs.h", "\nint a;\nint b;\n");
+    let main = write(&dir, "main.c", "int x;\n#include \"defs.h\"\n/* comment */\nint y;\n");
+
+    let preprocessed = Preprocessor::new().preprocess_file(&main).unwrap();
+    let text: Vec<&str> = preprocessed.text.split('\n').collect();
+    let line_of = |needle: &str| text.iter().position(|line| *line == needle).unwrap() + 1;
+
+    assert_eq!(preprocessed.location(line_of("int x;")), (main.as_str(), 1));
+    assert_eq!(preprocessed.location(line_of("int a;")), (header.as_str(), 2));
+    assert_eq!(preprocessed.location(line_of("int b;")), (header.as_str(), 3));
+    assert_eq!(preprocessed.location(line_of("int y;")), (main.as_str(), 4));
+}
+
+#[test]
+fn keeps_line_numbers_across_directives_and_continuations() {
+    let preprocessed = Preprocessor::new().preprocess("#define A \\\n  1\n#if A\nint x = A;\n#endif\n", "main.c").unwrap();
+    assert_eq!(preprocessed.text, "\n\n\nint x = 1;\n\n");
+    assert_eq!(preprocessed.location(4), ("main.c", 4));
+}
+
+#[test]
+fn expands_object_like_macros() {
+    assert_eq!(preprocess("#define N 10\n#define TWICE_N (N * 2)\nint a[TWICE_N];\n"), Ok(vec!["int a[(10 * 2)];".to_string()]));
+    // Not inside identifiers, strings or comments
+    assert_eq!(preprocess("#define N 10\nint NN = N; char *s = \"N\"; /* N */\n"),
+        Ok(vec!["int NN = 10; char *s = \"N\"; /* N */".to_string()]));
+}
+
+#[test]
+fn does_not_expand_a_macro_within_itself() {
+    assert_eq!(preprocess("#define x (x + 1)\nint y = x;\n"), Ok(vec!["int y = (x + 1);".to_string()]));
+}
+
+#[test]
+fn expands_function_like_macros() {
+    let source = "#define MAX(a, b) ((a) > (b) ? (a) : (b))\nint m = MAX(f(1, 2), 3);\nint MAX;\n";
+    assert_eq!(preprocess(source), Ok(vec!["int m = ((f(1, 2)) > (3) ? (f(1, 2)) : (3));".to_string(), "int MAX;".to_string()]));
+}
+
+#[test]
+fn stringizes_and_pastes_arguments() {
+    let source = "#define STR(x) #x\n#define CAT(a, b) a ## b\nchar *s = STR(1 + 2);\nint CAT(var, 3) = CAT(4, 2);\n";
+    assert_eq!(preprocess(source), Ok(vec!["char *s = \"1 + 2\";".to_string(), "int var3 = 42;".to_string()]));
+}
+
+#[test]
+fn undefines_macros() {
+    assert_eq!(preprocess("#define N 1\nint a = N;\n#undef N\nint b = N;\n"), Ok(vec!["int a = 1;".to_string(), "int b = N;".to_string()]));
+}
+
+#[test]
+fn uses_command_line_definitions() {
+    let mut preprocessor = Preprocessor::new();
+    preprocessor.define("DEBUG", "2");
+    assert_eq!(lines(&mut preprocessor, "#if DEBUG > 1\nint verbose;\n#endif\n", "main.c"), Ok(vec!["int verbose;".to_string()]));
+}
+
+#[test]
+fn evaluates_conditions() {
+    let source = "
+#if (1 + 2) * 3 == 9 && 7 / 2 == 3 && -1 < 0 && (1 << 4) == 16
+int arithmetic;
+#endif
+#if 0
+int zero;
+#elif 'a' == 97 && 0x10 == 16 && 010 == 8
+int literals;
+#else
+int other;
+#endif
+#if defined(UNDEFINED) || defined UNDEFINED || UNDEFINED
+int undefined;
+#endif
+#ifndef UNDEFINED
+int ifndef;
+#endif
+#if 1 ? 2 : 0
+int ternary;
+#endif
+";
+    assert_eq!(preprocess(source), Ok(vec!["int arithmetic;", "int literals;", "int ifndef;", "int ternary;"].into_iter().map(String::from).collect()));
+}
+
+#[test]
+fn short_circuits_logical_operators() {
+    assert_eq!(preprocess("#if 0 && 1 / 0\nint a;\n#elif 1 || 1 % 0\nint b;\n#endif\n"), Ok(vec!["int b;".to_string()]));
+    assert_eq!(preprocess("#if 1 ? 1 : 1 / 0\nint c;\n#endif\n"), Ok(vec!["int c;".to_string()]));
+    assert_eq!(preprocess("#if 1 && 1 / 0\n#endif\n"), Err("main.c:1: error: division by zero in #if expression".to_string()));
+}
+
+#[test]
+fn skips_nested_groups() {
+    let source = "#if 0\n#if 1\nint a;\n#else\nint b;\n#endif\n#error not reached\n#else\nint c;\n#endif\n";
+    assert_eq!(preprocess(source), Ok(vec!["int c;".to_string()]));
+}
+
+#[test]
+fn reports_error_directives() {
+    assert_eq!(preprocess("int a;\n#error unsupported target\n"), Err("main.c:2: error: #error unsupported target".to_string()));
+}
+
+#[test]
+fn reports_unbalanced_groups() {
+    assert_eq!(preprocess("#if 1\nint a;\n"), Err("main.c:1: error: #if without #endif".to_string()));
+    assert_eq!(preprocess("#endif\n"), Err("main.c:1: error: #endif without #if".to_string()));
+    assert_eq!(preprocess("#if 1\n#else\n#elif 1\n#endif\n"), Err("main.c:3: error: #elif after #else".to_string()));
+}
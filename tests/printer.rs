@@ -0,0 +1,35 @@
+mod common;
+
+use compiler::{ast::TranslationUnit, printer};
+
+use common::parse;
+
+// Debug output of the tree with every span left out, since printing moves things around
+fn without_spans(unit: &TranslationUnit) -> String {
+    let mut text = format!("{:?}", unit);
+    while let Some(start) = text.find("Span {") {
+        let end = start + text[start..].find('}').unwrap() + 1;
+        text.replace_range(start..end, "Span");
+    }
+    text
+}
+
+fn check_round_trip(source: &str) {
+    let unit = parse(source);
+    let printed = printer::print(&unit);
+    let reparsed = parse(&printed);
+    assert_eq!(without_spans(&unit), without_spans(&reparsed), "{}", printed);
+    assert_eq!(printer::print(&reparsed), printed);
+}
+
+#[test]
+fn print_round_trips_through_the_parser() {
+    check_round_trip(include_str!("c.c"));
+    check_round_trip(include_str!("round_trip.c"));
+}
+
+#[test]
+fn print_normalizes_layout_and_parentheses() {
+    let unit = parse("int f(int a){if(a)return((a+1)*2);else{a=-(-a);}while(a)a--;return a;}");
+    assert_eq!(printer::print(&unit), "int f(int a) {\n    if (a)\n        return (a + 1) * 2;\n    else {\n        a = - -a;\n    }\n    while (a)\n        a--;\n    return a;\n}\n");
+}
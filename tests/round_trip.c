@@ -0,0 +1,42 @@
+struct P { int x; char *name; int v[3]; };
+extern int printf(char *fmt, ...);
+static int counter = 0;
+char *names[2] = {"a\tb\n", "c\"d"};
+int sum(int a[], int n) {
+    int s = 0;
+    for (int i = 0, j = 1; i < n; i++) s += a[i] * (j + 1);
+    for (;;) break;
+    return s;
+}
+int main(void) {
+    struct P pt = {1, "x", {1, 2}};
+    struct P *pp = &pt;
+    int x = - -1, y = (x, 2), *q = &x;
+    unsigned long big = 10ul;
+    double d = 1.5e3;
+    char c = '\'';
+    if (x) if (y) x = 1; else x = 2;
+    else if (y) { y = 3; } else y = (int)d;
+    do x--; while (x > 0);
+    while (x < 10) { x += 2; }
+    pp->x = sizeof(int) + sizeof pt.x + pt.v[1];
+    x = y ? x : y ? 1 : 2;
+    x = (x ? &y : q) == 0;
+    *q = -(x + y) * ~y << 2 & 1 | !x;
+    printf("%d\n", sum(pt.v, 3), (x = 1, x), c, big);
+    return x < y && y != 0 || x >= 1;
+}
+unsigned char bytes[] = {0x1f, 017, '\0', '\x01'};
+const char *message = "bell\a" "tab\there";
+long f(char **argv, int, ...);
+int unspecified();
+int g(int *p, int n) {
+    int a[4][2];
+    (*p)++;
+    *p++ = -n++;
+    a[n][0] = &*p - p;
+    n = (long)(unsigned short)n + sizeof((char)n);
+    n = (n = 2) ? n : (n, 3);
+    n <<= 1, n |= 2;
+    return !!n - ~-n;
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use compiler::printer;
+
+use common::analyze;
+
+fn errors(source: &str) -> Vec<String> {
+    analyze(source).2
+}
+
+// Declarations and identifiers in the dump of `source`, with the symbols they resolve to
+fn resolved(source: &str) -> Vec<String> {
+    printer::dump(&analyze(source).0).lines()
+        .map(|line| line.split_whitespace().take(3).collect::<Vec<_>>().join(" "))
+        .filter(|line| ["Function", "Prototype", "Param", "Var", "Ident"].iter().any(|label| line.starts_with(label)))
+        .collect()
+}
+
+#[test]
+fn resolves_identifiers_to_the_innermost_declaration() {
+    let source = "int x;\nint main(int x) {\n    { int x = 2; x; }\n    return x;\n}\n";
+    assert_eq!(resolved(source), ["Var x #0", "Function main #1", "Param x #2", "Var x #3", "Ident x #3", "Ident x #2"]);
+    let (_, symbols, _) = analyze(source);
+    assert_eq!(symbols.symbols.len(), 4);
+    assert_eq!(symbols.symbols[3].span.to_string(), "3:11");
+
+    let source = "int x;\nint main(void) {\n    x;\n    for (int x = 0; x; ) { x; }\n    return x;\n}\n";
+    assert_eq!(resolved(source), ["Var x #0", "Function main #1", "Ident x #0", "Var x #2", "Ident x #2", "Ident x #2", "Ident x #0"]);
+}
+
+#[test]
+fn reports_undeclared_names() {
+    assert_eq!(errors("int main(void) {\n    return y;\n}\n"), ["2:12: error: use of undeclared identifier 'y'"]);
+    assert_eq!(errors("int main(void) {\n    h(y);\n}\n"), [
+        "2:5: error: call to undeclared function 'h'",
+        "2:7: error: use of undeclared identifier 'y'"
+    ]);
+    // A local is out of scope after its block
+    assert_eq!(errors("void f(void) {\n    { int a; }\n    a;\n}\n"), ["3:5: error: use of undeclared identifier 'a'"]);
+}
+
+#[test]
+fn reports_redeclarations_in_the_same_scope() {
+    assert_eq!(errors("void f(void) {\n    int x = 1;\n    int x;\n}\n"), ["3:9: error: redeclaration of 'x'"]);
+    // Parameters are in the scope of the body's outermost block
+    assert_eq!(errors("void f(int a) {\n    int a;\n}\n"), ["2:9: error: redeclaration of 'a'"]);
+    assert_eq!(errors("void f(int a, char *a) {}\n"), ["1:15: error: redeclaration of 'a'"]);
+    assert_eq!(errors("void f(int, int b) {}\n"), ["1:8: error: parameter name omitted in function definition"]);
+}
+
+#[test]
+fn warns_about_shadowing_across_for_init_and_params() {
+    assert_eq!(errors("int n;\nvoid f(int n) {}\n"), ["2:8: warning: declaration of 'n' shadows a previous declaration"]);
+    assert_eq!(errors("void f(int i) {\n    for (int i = 0; i; ) ;\n}\n"), ["2:14: warning: declaration of 'i' shadows a previous declaration"]);
+    // The body of a `for` is a scope inside the one of its declarations
+    assert_eq!(errors("void f(void) {\n    for (int i = 0; i; ) { int i; }\n}\n"), ["2:32: warning: declaration of 'i' shadows a previous declaration"]);
+    // Loop variables go out of scope after the loop
+    assert_eq!(errors("void f(void) {\n    for (int i = 0; i; ) ;\n    int i;\n}\n"), Vec::<String>::new());
+}
+
+#[test]
+fn merges_tentative_definitions() {
+    assert_eq!(errors("int a;\nint a;\nint a = 1;\nint a;\n"), Vec::<String>::new());
+    assert_eq!(resolved("int a;\nint a = 1;\nint f(void) { return a; }\n"), ["Var a #0", "Var a #0", "Function f #1", "Ident a #0"]);
+    assert_eq!(errors("int b = 1;\nint b = 2;\n"), ["2:5: error: redefinition of 'b'"]);
+    assert_eq!(errors("int c;\nlong c;\n"), ["2:6: error: conflicting types for 'c'"]);
+    assert_eq!(errors("int d;\nint d(void);\n"), ["2:1: error: redeclaration of 'd' as a different kind of symbol"]);
+}
+
+#[test]
+fn links_extern_declarations_to_the_definition() {
+    assert_eq!(errors("extern int a;\nint a = 1;\nextern int a;\n"), Vec::<String>::new());
+    assert_eq!(errors("extern int b = 1;\nint b = 2;\n"), Vec::<String>::new());
+    assert_eq!(errors("extern int c;\nextern char c;\n"), ["2:13: error: conflicting types for 'c'"]);
+}
+
+#[test]
+fn links_block_scope_extern_declarations_to_file_scope() {
+    assert_eq!(resolved("int g;\nvoid f(void) {\n    int g;\n    {\n        extern int g;\n        g;\n    }\n}\n"), [
+        "Var g #0", "Function f #1", "Var g #2", "Var g #0", "Ident g #0"
+    ]);
+    assert_eq!(errors("long g;\nvoid f(void) {\n    extern int g;\n}\n"), ["3:16: error: conflicting types for 'g'"]);
+    assert_eq!(errors("int g(void);\nvoid f(void) {\n    extern int g;\n}\n"), ["3:16: error: redeclaration of 'g' as a different kind of symbol"]);
+    assert_eq!(errors("void f(void) {\n    int x;\n    extern int x;\n}\n"), ["3:16: error: redeclaration of 'x'"]);
+    assert_eq!(errors("void f(void) {\n    extern int x;\n    extern int x;\n    x;\n}\n"), Vec::<String>::new());
+}
+
+#[test]
+fn checks_function_redeclarations() {
+    assert_eq!(errors("int f(int a);\nint f(int b) { return b; }\nint f(int);\n"), Vec::<String>::new());
+    assert_eq!(errors("int f(void) { return 0; }\nint f(void) { return 1; }\n"), ["2:1: error: redefinition of 'f'"]);
+    assert_eq!(errors("int f(int a, int b);\nlong f(int a, int b);\n"), ["2:1: error: conflicting types for 'f'"]);
+    assert_eq!(errors("int f(int a[]);\nint f(int *a);\n"), Vec::<String>::new());
+}
+
+#[test]
+fn checks_the_number_of_arguments() {
+    let source = "int f(int a, int b);\nint g(void);\nvoid h(void) {\n    f(1);\n    f(1, 2, 3);\n    g(1);\n    f(1, 2);\n}\n";
+    assert_eq!(errors(source), [
+        "4:5: error: function 'f' expects 2 arguments, found 1",
+        "5:5: error: function 'f' expects 2 arguments, found 3",
+        "6:5: error: function 'g' expects 0 arguments, found 1"
+    ]);
+}
+
+#[test]
+fn checks_the_number_of_variadic_arguments() {
+    let source = "int printf(char *format, ...);\nvoid f(void) {\n    printf();\n    printf(\"\");\n    printf(\"%d %d\", 1, 2);\n}\n";
+    assert_eq!(errors(source), ["3:5: error: function 'printf' expects at least 1 argument, found 0"]);
+}
+
+#[test]
+fn does_not_check_calls_to_functions_without_a_prototype() {
+    assert_eq!(errors("int f();\nvoid g(void) {\n    f();\n    f(1, 2);\n}\n"), Vec::<String>::new());
+    // Declarations without a prototype agree with any parameters
+    assert_eq!(errors("int f();\nint f(int a, char *b);\nint f();\n"), Vec::<String>::new());
+    assert_eq!(errors("int f();\nlong f(int a);\n"), ["2:1: error: conflicting types for 'f'"]);
+    // A later prototype is checked
+    assert_eq!(errors("int f();\nint f(int a) { return a; }\nvoid g(void) {\n    f();\n}\n"), ["4:5: error: function 'f' expects 1 argument, found 0"]);
+}
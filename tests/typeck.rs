@@ -0,0 +1,149 @@
+mod common;
+
+use compiler::ast::{ExternalDecl, StmtKind};
+
+use common::check;
+
+// Type errors of `body` in a function returning `int`, after the file-scope declarations `decls`
+fn errors(decls: &str, body: &str) -> Vec<String> {
+    check(&format!("{}\nint test(void) {{\n    {}\n    return 0;\n}}\n", decls, body)).1
+}
+
+// Type of the expression `expr` after the declarations `decls`
+fn type_of(decls: &str, expr: &str) -> String {
+    let (unit, errors) = check(&format!("{}\nvoid test(void) {{\n    {};\n}}\n", decls, expr));
+    assert!(errors.is_empty(), "{:?}", errors);
+    let Some(ExternalDecl::Function(function)) = unit.items.last() else { panic!("{:?}", unit.items.last()) };
+    let StmtKind::Expr(expr) = &function.body.stmts[0].kind else { panic!("{:?}", function.body.stmts[0]) };
+    expr.ty.as_ref().map_or("none".to_string(), |ty| ty.to_string())
+}
+
+#[test]
+fn annotates_expressions_with_their_types() {
+    assert_eq!(type_of("char *s;", "s[0]"), "char");
+    // `char` operands are promoted, and `long` holds every `unsigned int`
+    assert_eq!(type_of("char *s; long n;", "s[0] + n * 2u"), "long");
+}
+
+#[test]
+fn reports_assignments_to_non_lvalues() {
+    assert_eq!(errors("char c;", "1 = c;"), ["3:5: error: expression is not assignable"]);
+    assert_eq!(errors("int a[2];", "a = 0;"), ["3:5: error: array type 'int[2]' is not assignable"]);
+}
+
+#[test]
+fn reports_indirection_through_non_pointers() {
+    assert_eq!(errors("char c;", "*c;"), ["3:5: error: indirection requires pointer operand ('char' invalid)"]);
+}
+
+#[test]
+fn reports_void_values_that_are_used() {
+    assert_eq!(errors("void v(void); char c;", "c = v();"), ["3:9: error: void value not ignored as it ought to be"]);
+    assert_eq!(errors("void v(void);", "v();"), Vec::<String>::new());
+}
+
+#[test]
+fn warns_about_pointer_integer_conversions() {
+    assert_eq!(errors("char c;", "c = &c;"), ["3:9: warning: incompatible pointer to integer conversion assigning to 'char' from 'char *'"]);
+}
+
+#[test]
+fn reports_member_access_on_non_structs() {
+    assert_eq!(errors("char c;", "c.x;"), ["3:5: error: member reference base type 'char' is not a structure"]);
+}
+
+#[test]
+fn checks_return_values() {
+    let (_, errors) = check("int f(void) {\n    return;\n}\nvoid g(void) {\n    return 1;\n}\n");
+    assert_eq!(errors, [
+        "2:5: error: non-void function 'f' should return a value",
+        "5:5: error: void function 'g' should not return a value"
+    ]);
+}
+
+const ARITHMETIC: &str = "char c; short s; int i; unsigned u; long l; unsigned long ul; double d;";
+const POINTERS: &str = "int i; int *p; int *q; char *s; void *v; int a[3];";
+const STRUCTS: &str = "struct point { int x; char *name; }; struct point pt; struct point *pp; struct other *o; int i;";
+
+#[test]
+fn applies_the_usual_arithmetic_conversions() {
+    assert_eq!(type_of(ARITHMETIC, "c + s"), "int");
+    assert_eq!(type_of(ARITHMETIC, "u + i"), "unsigned int");
+    // `long` holds every `unsigned int`, but not every `unsigned long`
+    assert_eq!(type_of(ARITHMETIC, "u + l"), "long");
+    assert_eq!(type_of(ARITHMETIC, "l * 2u"), "long");
+    assert_eq!(type_of(ARITHMETIC, "ul + i"), "unsigned long");
+    assert_eq!(type_of(ARITHMETIC, "l - ul"), "unsigned long");
+    assert_eq!(type_of(ARITHMETIC, "u + d"), "double");
+    // Shifts have the promoted type of their left operand
+    assert_eq!(type_of(ARITHMETIC, "c << l"), "int");
+    assert_eq!(type_of(ARITHMETIC, "u >> l"), "unsigned int");
+    assert_eq!(errors(ARITHMETIC, "d % i;"), ["3:5: error: invalid operands to binary expression ('double' and 'int')"]);
+}
+
+#[test]
+fn types_pointer_arithmetic() {
+    assert_eq!(type_of(POINTERS, "p + 1"), "int *");
+    assert_eq!(type_of(POINTERS, "1 + p"), "int *");
+    assert_eq!(type_of(POINTERS, "p - i"), "int *");
+    assert_eq!(type_of(POINTERS, "a + 1"), "int *");
+    assert_eq!(type_of(POINTERS, "p - q"), "long");
+    assert_eq!(type_of(POINTERS, "p - a"), "long");
+    assert_eq!(errors(POINTERS, "p + q;"), ["3:5: error: invalid operands to binary expression ('int *' and 'int *')"]);
+    assert_eq!(errors(POINTERS, "p - s;"), ["3:5: error: invalid operands to binary expression ('int *' and 'char *')"]);
+    assert_eq!(errors(POINTERS, "1 - p;"), ["3:5: error: invalid operands to binary expression ('int' and 'int *')"]);
+}
+
+#[test]
+fn types_comparisons() {
+    assert_eq!(type_of(ARITHMETIC, "u < l"), "int");
+    assert_eq!(type_of(POINTERS, "p < q"), "int");
+    assert_eq!(type_of(POINTERS, "p == v"), "int");
+    assert_eq!(type_of(POINTERS, "0 != p"), "int");
+    assert_eq!(type_of(POINTERS, "p && i"), "int");
+    assert_eq!(errors(POINTERS, "p < v;"), ["3:5: error: invalid operands to binary expression ('int *' and 'void *')"]);
+    assert_eq!(errors(POINTERS, "p == s;"), ["3:5: error: invalid operands to binary expression ('int *' and 'char *')"]);
+    assert_eq!(errors(POINTERS, "p == 1;"), ["3:5: error: invalid operands to binary expression ('int *' and 'int')"]);
+    assert_eq!(errors(POINTERS, "p < 0;"), ["3:5: error: invalid operands to binary expression ('int *' and 'int')"]);
+}
+
+#[test]
+fn converts_null_pointer_constants() {
+    assert_eq!(errors(POINTERS, "p = 0;\n    p = (void *)0;\n    p = v;\n    v = s;"), Vec::<String>::new());
+    assert_eq!(errors(POINTERS, "p = 1;"), ["3:9: warning: incompatible integer to pointer conversion assigning to 'int *' from 'int'"]);
+    assert_eq!(errors(POINTERS, "p = i - i;"), ["3:9: warning: incompatible integer to pointer conversion assigning to 'int *' from 'int'"]);
+    assert_eq!(errors(POINTERS, "p = s;"), ["3:9: warning: incompatible pointer types assigning to 'int *' from 'char *'"]);
+}
+
+#[test]
+fn types_conditional_operators() {
+    assert_eq!(type_of(ARITHMETIC, "i ? c : l"), "long");
+    assert_eq!(type_of(ARITHMETIC, "i ? u : d"), "double");
+    assert_eq!(type_of(POINTERS, "i ? p : q"), "int *");
+    assert_eq!(type_of(POINTERS, "i ? p : v"), "void *");
+    assert_eq!(type_of(POINTERS, "i ? 0 : p"), "int *");
+    assert_eq!(type_of(POINTERS, "i ? a : p"), "int *");
+    assert_eq!(type_of(STRUCTS, "i ? pt : *pp"), "struct point");
+    assert_eq!(errors(POINTERS, "i ? p : s;"), ["3:5: error: incompatible operand types ('int *' and 'char *')"]);
+    assert_eq!(errors(POINTERS, "i ? p : 1;"), ["3:5: error: incompatible operand types ('int *' and 'int')"]);
+}
+
+#[test]
+fn types_struct_member_access() {
+    assert_eq!(type_of(STRUCTS, "pt.x"), "int");
+    assert_eq!(type_of(STRUCTS, "pp->name"), "char *");
+    assert_eq!(type_of(STRUCTS, "(&pt)->name[0]"), "char");
+    assert_eq!(errors(STRUCTS, "pt.y;"), ["3:5: error: no member named 'y' in 'struct point'"]);
+    assert_eq!(errors(STRUCTS, "pt->x;"), ["3:5: error: member reference type 'struct point' is not a pointer"]);
+    assert_eq!(errors(STRUCTS, "o->x;"), ["3:5: error: incomplete definition of type 'struct other'"]);
+    assert_eq!(errors(STRUCTS, "i.x;"), ["3:5: error: member reference base type 'int' is not a structure"]);
+}
+
+#[test]
+fn converts_the_result_of_compound_assignments() {
+    assert_eq!(errors(POINTERS, "p += 1;\n    p -= i;\n    i += 2;\n    i <<= 1;"), Vec::<String>::new());
+    assert_eq!(errors("char c; char *p;", "c += p;"), ["3:10: warning: incompatible pointer to integer conversion assigning to 'char' from 'char *'"]);
+    assert_eq!(errors(POINTERS, "p -= q;"), ["3:10: warning: incompatible integer to pointer conversion assigning to 'int *' from 'long'"]);
+    assert_eq!(errors(POINTERS, "p *= 2;"), ["3:5: error: invalid operands to binary expression ('int *' and 'int')"]);
+    assert_eq!(type_of("char c;", "c += 1"), "char");
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use compiler::{header::Header, output::{self, EmitOptions, OutputFormat}, verifier};
+
+use common::assemble;
+
+const SOURCE: &str = "
+.entry .start
+.word 1, 2, 3
+.main:
+    mov r0, 0
+.start:
+    inc r0
+    push r0
+    add sp, sp, 4
+    cmp r0, 5
+    blt .start
+    call .main
+    calljs 1
+";
+
+fn errors(words: &[u32]) -> Vec<String> {
+    verifier::verify_code(words).iter().map(|err| err.to_string()).collect()
+}
+
+fn image_errors(words: &[u32]) -> Vec<String> {
+    verifier::verify_image(words).iter().map(|err| err.to_string()).collect()
+}
+
+fn output_errors(text: &str) -> Vec<String> {
+    verifier::verify_output(text).iter().map(|err| err.to_string()).collect()
+}
+
+// Header for `code`, starting at `entry`
+fn image(entry: u32, code: &[u32]) -> Vec<u32> {
+    let header = Header { version: 1, entry, stack: 0x8000, code_size: code.len() as u32, data_size: 0 };
+    [header.to_words(), code.to_vec()].concat()
+}
+
+#[test]
+fn accepts_every_emitted_format() {
+    let program = assemble(SOURCE);
+
+    for format in [OutputFormat::Fragment, OutputFormat::Module, OutputFormat::Json, OutputFormat::TypedArray] {
+        for (symbols, data) in [(false, false), (true, false), (false, true), (true, true)] {
+            let text = output::emit(&program, &EmitOptions { format, symbols, data }).unwrap();
+            assert_eq!(output_errors(&text), Vec::<String>::new(), "{:?} symbols={} data={}:\n{}", format, symbols, data, text);
+        }
+    }
+}
+
+#[test]
+fn checks_the_header_of_emitted_images() {
+    let program = assemble(SOURCE);
+    let text = output::emit(&program, &EmitOptions { format: OutputFormat::TypedArray, symbols: true, data: false }).unwrap();
+
+    // The entry offset, 3, is the third header word
+    let broken = text.replacen("1397574230, 1, 3,", "1397574230, 1, 1,", 1);
+    assert_eq!(output_errors(&broken), ["error: offset 1: entry point is in the middle of an instruction"]);
+
+    let broken = text.replacen("1397574230, 1,", "1397574230, 7,", 1);
+    assert_eq!(output_errors(&broken), ["error: offset 0: header: unsupported format version 7 (expected 1)"]);
+}
+
+#[test]
+fn reports_words_that_do_not_fit() {
+    assert_eq!(output_errors("[20, 4294967296]"), ["error: offset 0: invalid word '4294967296'"]);
+}
+
+#[test]
+fn reports_bad_opcodes_and_operands() {
+    assert_eq!(errors(&[99]), ["error: offset 0: unknown opcode 99"]);
+    assert_eq!(errors(&[20, 128]), ["error: offset 0: INC operand 0: register 128 out of range"]);
+    assert_eq!(errors(&[6, 9, 0]), ["error: offset 0: BRANCH_COND_CONST operand 0: unknown branch condition 9"]);
+    assert_eq!(errors(&[0, 1]), ["error: offset 0: MOV_CONST needs 2 operands but the code ends after 1"]);
+}
+
+#[test]
+fn reports_targets_off_instruction_boundaries() {
+    assert_eq!(errors(&[20, 0, 4, 1]), ["error: offset 2: BRANCH_CONST target 1 is in the middle of an instruction"]);
+    assert_eq!(errors(&[8, 7]), ["error: offset 0: CALL_CONST target 7 is outside of the code"]);
+    assert_eq!(errors(&[20, 0, 4, 0]), Vec::<String>::new());
+}
+
+#[test]
+fn stops_at_an_unknown_opcode_without_checking_the_entry() {
+    assert_eq!(image_errors(&image(3, &[20, 0, 99, 22])), ["error: offset 2: unknown opcode 99"]);
+    assert_eq!(image_errors(&image(1, &[20, 0, 22])), ["error: offset 1: entry point is in the middle of an instruction"]);
+    assert_eq!(image_errors(&image(2, &[20, 0, 22])), Vec::<String>::new());
+}
+
+#[test]
+fn checks_the_text_form() {
+    assert_eq!(output_errors("Op.INC, 0,\nOp.CLEAR_FLAGS,\n"), Vec::<String>::new());
+    assert_eq!(output_errors("Op.INC,\n"), ["error: offset 0: INC expects 1 operands, found 0"]);
+    assert_eq!(output_errors("Op.INC, 0, 1,\n"), ["error: offset 0: INC expects 1 operands, found extra value 1"]);
+    assert_eq!(output_errors("Op.NOPE,\n"), ["error: offset 0: unknown opcode Op.NOPE"]);
+    assert_eq!(output_errors("Op.BRANCH_COND_CONST, BranchType.LT, 0,\n"), Vec::<String>::new());
+}